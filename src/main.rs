@@ -1,7 +1,9 @@
 extern crate core;
 
+#[allow(unused_imports)]
 use core::num;
 use std::fmt::Formatter;
+#[allow(unused_imports)]
 use std::string::ParseError;
 
 //region 18.错误处理
@@ -16,6 +18,15 @@ use std::string::ParseError;
 //      也可以用 unwrap，然后使用 expect
 //endregion
 fn main() {
+    // `cargo run -- repl` 进入交互式计算器（见最后的“交互式计算器”一节），不跑下面的示例
+    if std::env::args().nth(1).as_deref() == Some("repl") {
+        let stdin = std::io::stdin();
+        let mut stdout = std::io::stdout();
+        if let Err(e) = run_repl(stdin.lock(), &mut stdout) {
+            eprintln!("repl stopped: {}", e);
+        }
+        return;
+    }
     // 在标准库当中有个叫 Option<T> 的枚举类型，用于有“不存在”的可能性的情况。
     // 它表现为以下两个“option”中的一个：
     //      Some(T) : 找到一个属于 T 类型的元素
@@ -98,6 +109,7 @@ fn main() {
     #[derive(Clone, Copy)]
     struct PhoneNumber {
         area_code: Option<u8>,
+        #[allow(dead_code)]
         number: u32,
     }
     impl Person {
@@ -124,7 +136,7 @@ fn main() {
     // 这时可以使用 组合算子(combinator)，以模块化的风格来管理控制流。
     // Option有一个内置方法 map()，多个不同的 map() 调用可以串起来
     // #![allow(dead_code)]
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     enum Food {
         Apple,
         Carrot,
@@ -138,6 +150,7 @@ fn main() {
     struct Cooked(Food);
 
     // 削皮，如果没有食物，就返回 `None`，否则返回削好皮的食物
+    #[allow(clippy::manual_map)]
     fn peel(food: Option<Food>) -> Option<Peeled> {
         match food {
             Some(food) => Some(Peeled(food)),
@@ -145,6 +158,7 @@ fn main() {
         }
     }
     // 切食物，如果没有食物，返回 `None`，否则返回切好的食物
+    #[allow(clippy::manual_map)]
     fn chop(peeled: Option<Peeled>) -> Option<Chopped> {
         match peeled {
             Some(Peeled(food)) => Some(Chopped(food)),
@@ -156,6 +170,7 @@ fn main() {
         chopped.map(|Chopped(food)| Cooked(food))
     }
     // 这个函数将削皮、切块、烹饪一条龙
+    #[allow(clippy::redundant_closure)]
     fn process(food: Option<Food>) -> Option<Cooked> {
         food.map(|f| Peeled(f))
             .map(|Peeled(f)| Chopped(f))
@@ -180,6 +195,330 @@ fn main() {
     eat(cooked_carrot);
     //endregion
 
+    //region 18.2.2.扩展：批量备餐
+    println!("\n\n=====18.2.2.扩展：批量备餐=====");
+    // 上面是一样一样地 cook(chop(peel(...)))，食材一多就很啰嗦。
+    // 这里把 削皮→切块→烹饪 三道工序套在一整批食材上，可以选择用 std::thread 开几个线程并行处理。
+    // 结果按输入的顺序一一对应，每一项还是 Option<Cooked>，另外统计成功和失败的数量。
+    use std::thread;
+    struct BatchReport {
+        outcomes: Vec<Option<Cooked>>,
+        succeeded: usize,
+        failed: usize,
+    }
+    fn prepare_one(food: Option<Food>) -> Option<Cooked> {
+        cook(chop(peel(food)))
+    }
+    fn prepare_batch(foods: Vec<Option<Food>>, workers: usize) -> BatchReport {
+        let outcomes: Vec<Option<Cooked>> = if workers <= 1 || foods.len() <= 1 {
+            foods.into_iter().map(prepare_one).collect()
+        } else {
+            // 按顺序切成若干块，每块交给一个线程；按启动顺序 join，拼回来的顺序就和输入一致
+            let chunk_size = foods.len().div_ceil(workers);
+            let mut rest = foods.into_iter();
+            let mut handles = Vec::new();
+            loop {
+                let chunk: Vec<Option<Food>> = rest.by_ref().take(chunk_size).collect();
+                if chunk.is_empty() {
+                    break;
+                }
+                handles.push(thread::spawn(move || {
+                    chunk.into_iter().map(prepare_one).collect::<Vec<_>>()
+                }));
+            }
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("batch worker panicked"))
+                .collect()
+        };
+        let succeeded = outcomes.iter().filter(|cooked| cooked.is_some()).count();
+        let failed = outcomes.len() - succeeded;
+        BatchReport { outcomes, succeeded, failed }
+    }
+
+    let batch = vec![Some(Food::Apple), Some(Food::Carrot), None, Some(Food::Potato), Some(Food::Apple)];
+    let report = prepare_batch(batch, 3);
+    for (i, outcome) in report.outcomes.iter().enumerate() {
+        println!("#{}: {:?}", i, outcome);
+    }
+    println!("成功 {} 份，失败 {} 份", report.succeeded, report.failed);
+    assert_eq!((report.succeeded, report.failed), (4, 1));
+    assert!(report.outcomes[2].is_none());
+    //endregion
+
+    //region 18.2.扩展：模拟时钟
+    println!("\n\n=====18.2.扩展：模拟时钟=====");
+    // 吃饭、排菜单、点单、食材过期都和时间有关。如果直接读系统时间，每次运行的结果都不一样，
+    // 也就没法在 assert 里写死期望值。这里用一个模拟时钟：从第 0 天（星期一）0 点开始，
+    // 只有显式调用 advance_* 时间才会往前走，所以每个场景都能原样重现。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+    struct SimTime {
+        minutes: u32,
+    }
+    impl SimTime {
+        fn at(day: u32, hour: u32) -> SimTime {
+            SimTime { minutes: (day * 24 + hour) * 60 }
+        }
+        fn day(self) -> u32 {
+            self.minutes / (24 * 60)
+        }
+        fn hour(self) -> u32 {
+            self.minutes / 60 % 24
+        }
+        fn minute(self) -> u32 {
+            self.minutes % 60
+        }
+        fn weekday(self) -> Day {
+            Day::ALL[self.day() as usize % Day::ALL.len()]
+        }
+    }
+    impl fmt::Display for SimTime {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "day {} ({:?}) {:02}:{:02}", self.day(), self.weekday(), self.hour(), self.minute())
+        }
+    }
+    #[derive(Debug, Default)]
+    struct SimClock {
+        now: SimTime,
+    }
+    impl SimClock {
+        fn starting_at(day: u32, hour: u32) -> SimClock {
+            SimClock { now: SimTime::at(day, hour) }
+        }
+        fn now(&self) -> SimTime {
+            self.now
+        }
+        fn advance_minutes(&mut self, minutes: u32) {
+            self.now.minutes += minutes;
+        }
+        fn advance_hours(&mut self, hours: u32) {
+            self.advance_minutes(hours * 60);
+        }
+        fn advance_days(&mut self, days: u32) {
+            self.advance_hours(days * 24);
+        }
+    }
+
+    let mut clock = SimClock::starting_at(0, 8);
+    clock.advance_hours(30);
+    println!("now: {}", clock.now());
+    assert_eq!((clock.now().day(), clock.now().hour(), clock.now().weekday()), (1, 14, Day::Tuesday));
+    //endregion
+
+    //region 18.2.2.扩展：营养记录
+    println!("\n\n=====18.2.2.扩展：营养记录=====");
+    // eat() 只会说一句 "Mmm, I love ..."，吃了什么、吃了多少都没留下。
+    // 这里加一本饮食日记：每吃一份 Cooked 就记一笔，热量和三大营养素按食物查表，
+    // 再按加工阶段打个折（削皮会去掉一部分，烹饪也有损耗），可以按天汇总，也可以查什么时候吃过什么。
+    use std::ops::AddAssign;
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Stage {
+        Raw,
+        Peeled,
+        Chopped,
+        Cooked,
+    }
+    // 每个阶段保留下来的营养百分比
+    fn retained_percent(stage: Stage) -> f32 {
+        match stage {
+            Stage::Raw => 100.0,
+            Stage::Peeled => 92.0,
+            Stage::Chopped => 92.0,
+            Stage::Cooked => 85.0,
+        }
+    }
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    struct Nutrition {
+        calories: f32,
+        protein: f32,
+        fat: f32,
+        carbs: f32,
+    }
+    impl Nutrition {
+        fn scaled(self, percent: f32) -> Nutrition {
+            let k = percent / 100.0;
+            Nutrition {
+                calories: self.calories * k,
+                protein: self.protein * k,
+                fat: self.fat * k,
+                carbs: self.carbs * k,
+            }
+        }
+    }
+    impl AddAssign for Nutrition {
+        fn add_assign(&mut self, other: Nutrition) {
+            self.calories += other.calories;
+            self.protein += other.protein;
+            self.fat += other.fat;
+            self.carbs += other.carbs;
+        }
+    }
+    impl fmt::Display for Nutrition {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{:.0} kcal (protein {:.1}g, fat {:.1}g, carbs {:.1}g)",
+                   self.calories, self.protein, self.fat, self.carbs)
+        }
+    }
+    // 每份生食材的营养（按 100g 算）
+    fn raw_nutrition(food: Food) -> Nutrition {
+        match food {
+            Food::Apple => Nutrition { calories: 52.0, protein: 0.3, fat: 0.2, carbs: 13.8 },
+            Food::Carrot => Nutrition { calories: 41.0, protein: 0.9, fat: 0.2, carbs: 9.6 },
+            Food::Potato => Nutrition { calories: 77.0, protein: 2.0, fat: 0.1, carbs: 17.5 },
+        }
+    }
+    fn nutrition_of(food: Food, stage: Stage) -> Nutrition {
+        raw_nutrition(food).scaled(retained_percent(stage))
+    }
+
+    #[derive(Debug)]
+    struct DiaryEntry {
+        at: SimTime,
+        food: Food,
+        nutrition: Nutrition,
+    }
+    #[derive(Debug, Default)]
+    struct FoodDiary {
+        entries: Vec<DiaryEntry>,
+    }
+    impl FoodDiary {
+        fn record(&mut self, at: SimTime, cooked: &Cooked) {
+            let Cooked(food) = *cooked;
+            self.entries.push(DiaryEntry { at, food, nutrition: nutrition_of(food, Stage::Cooked) });
+        }
+        fn eaten_on(&self, day: u32) -> impl Iterator<Item = &DiaryEntry> {
+            self.entries.iter().filter(move |entry| entry.at.day() == day)
+        }
+        fn daily_total(&self, day: u32) -> Nutrition {
+            let mut total = Nutrition::default();
+            for entry in self.eaten_on(day) {
+                total += entry.nutrition;
+            }
+            total
+        }
+        // 某样食物都是什么时候吃的
+        fn when_eaten(&self, food: Food) -> Vec<SimTime> {
+            self.entries.iter().filter(|entry| entry.food == food).map(|entry| entry.at).collect()
+        }
+    }
+    // 和 eat() 一样，只是吃下去的东西会记到日记里
+    fn eat_logged(food: Option<Cooked>, diary: &mut FoodDiary, clock: &SimClock) {
+        match food {
+            Some(food) => {
+                println!("Mmm, I love {:?}", food);
+                diary.record(clock.now(), &food);
+            }
+            None => println!("Oh no!It wasn't edible."),
+        }
+    }
+
+    let mut diary = FoodDiary::default();
+    let mut clock = SimClock::starting_at(1, 8);
+    eat_logged(cook(chop(peel(Some(Food::Apple)))), &mut diary, &clock);
+    clock.advance_hours(4);
+    eat_logged(process(Some(Food::Potato)), &mut diary, &clock);
+    clock.advance_hours(6);
+    eat_logged(process(None), &mut diary, &clock);
+    clock.advance_hours(15);
+    eat_logged(process(Some(Food::Apple)), &mut diary, &clock);
+    for entry in diary.eaten_on(1) {
+        println!("{} {:?}: {}", entry.at, entry.food, entry.nutrition);
+    }
+    println!("day 1 total: {}", diary.daily_total(1));
+    let apple_times: Vec<String> = diary.when_eaten(Food::Apple).iter().map(|at| at.to_string()).collect();
+    println!("apple eaten at: {}", apple_times.join(", "));
+    assert_eq!(diary.eaten_on(1).count(), 2);
+    assert_eq!(diary.when_eaten(Food::Apple), vec![SimTime::at(1, 8), SimTime::at(2, 9)]);
+    // 生吃不打折
+    assert_eq!(nutrition_of(Food::Carrot, Stage::Raw), raw_nutrition(Food::Carrot));
+    //endregion
+
+    //region 18.2.2.扩展：组合菜谱
+    println!("\n\n=====18.2.2.扩展：组合菜谱=====");
+    // 上面的流程永远是一样 Food 变成一样 Cooked。真正做菜往往要好几样处理好的食材，
+    // 比如 切好的胡萝卜 + 削好皮的土豆。Recipe 列出需要哪些食材、各要处理到哪一步，
+    // 凑齐了就做成一道组合菜 Dish，缺了哪样就在 Err 里指出来。
+    #[derive(Debug)]
+    enum Prepared {
+        Peeled(Peeled),
+        Chopped(Chopped),
+    }
+    impl Prepared {
+        fn food(&self) -> Food {
+            match *self {
+                Prepared::Peeled(Peeled(food)) | Prepared::Chopped(Chopped(food)) => food,
+            }
+        }
+        fn stage(&self) -> Stage {
+            match *self {
+                Prepared::Peeled(_) => Stage::Peeled,
+                Prepared::Chopped(_) => Stage::Chopped,
+            }
+        }
+    }
+    impl From<Peeled> for Prepared {
+        fn from(peeled: Peeled) -> Prepared {
+            Prepared::Peeled(peeled)
+        }
+    }
+    impl From<Chopped> for Prepared {
+        fn from(chopped: Chopped) -> Prepared {
+            Prepared::Chopped(chopped)
+        }
+    }
+    #[derive(Debug)]
+    struct Dish {
+        name: &'static str,
+        ingredients: Vec<Food>,
+    }
+    #[derive(Debug, PartialEq)]
+    struct MissingIngredient {
+        recipe: &'static str,
+        food: Food,
+        stage: Stage,
+    }
+    impl fmt::Display for MissingIngredient {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} needs {:?} {:?}, but none was provided", self.recipe, self.stage, self.food)
+        }
+    }
+    impl error::Error for MissingIngredient {}
+    struct Recipe {
+        name: &'static str,
+        requires: Vec<(Food, Stage)>,
+    }
+    impl Recipe {
+        // 按菜谱顺序逐样取用，用过的食材不能重复算；多出来的食材不影响
+        fn combine(&self, mut ingredients: Vec<Prepared>) -> Result<Dish, MissingIngredient> {
+            let mut used = Vec::new();
+            for &(food, stage) in &self.requires {
+                let index = ingredients
+                    .iter()
+                    .position(|item| item.food() == food && item.stage() == stage)
+                    .ok_or(MissingIngredient { recipe: self.name, food, stage })?;
+                used.push(ingredients.swap_remove(index).food());
+            }
+            Ok(Dish { name: self.name, ingredients: used })
+        }
+    }
+
+    let stew = Recipe {
+        name: "carrot & potato stew",
+        requires: vec![(Food::Carrot, Stage::Chopped), (Food::Potato, Stage::Peeled)],
+    };
+    let chopped_carrot = chop(peel(Some(Food::Carrot))).map(Prepared::from);
+    let peeled_potato = peel(Some(Food::Potato)).map(Prepared::from);
+    let ingredients: Vec<Prepared> = vec![chopped_carrot, peeled_potato].into_iter().flatten().collect();
+    let served = stew.combine(ingredients).map(|dish| format!("{} made of {:?}", dish.name, dish.ingredients));
+    report_result("Served", served.as_ref(), ReportStyle::Plain);
+    let only_carrot = vec![Prepared::from(Chopped(Food::Carrot))];
+    let missing = stew.combine(only_carrot);
+    if let Err(ref e) = missing {
+        report_result("Served", Err::<String, _>(e), ReportStyle::Plain);
+    }
+    assert_eq!(missing.unwrap_err(), MissingIngredient { recipe: stew.name, food: Food::Potato, stage: Stage::Peeled });
+    //endregion
+
     //region 18.2.3.组合算子：and_then
     println!("\n\n=====18.2.3.组合算子：and_then=====");
     // map() 以链接调用的方式来简化 match 语句，如果返回类型是 Option<T> 的函数作为 map()
@@ -190,28 +529,177 @@ fn main() {
     // 在下面的例子中，cookable-v2() 会产生一个 Option<Food>，如果在这里使用 map() 而不是
     // and_then() 将会得到 Option<Option<Food>> ，这对 eat() 来说是一个无效类型
     // #![allow(dead_code)]
-    #[derive(Debug)] enum FoodV2 { CordonBleu, Steak, Sushi }
-    #[derive(Debug)] enum Day {Monday, Tuesday, Wednesday}
-    // 我们没有制作寿司所需的原材料(ingredient)
-    fn have_ingredients(food: FoodV2) -> Option<FoodV2> {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)] enum FoodV2 { CordonBleu, Steak, Sushi }
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum Day { Monday, Tuesday, Wednesday, Thursday, Friday, Saturday, Sunday }
+    impl Day {
+        const ALL: [Day; 7] = [
+            Day::Monday, Day::Tuesday, Day::Wednesday, Day::Thursday, Day::Friday, Day::Saturday, Day::Sunday,
+        ];
+    }
+
+    // 厨房库存：每样原材料有多少、已经给哪些菜预留了多少。
+    // 每道菜需要哪些原材料、各要多少，由 bill_of_materials 给出（物料清单）。
+    use std::collections::HashMap;
+    type Ingredient = &'static str;
+    fn bill_of_materials(food: FoodV2) -> Vec<(Ingredient, u32)> {
         match food {
-            FoodV2::Sushi => None,
-            _ => Some(food),
+            FoodV2::CordonBleu => vec![("chicken breast", 1), ("ham", 1), ("cheese", 1), ("breadcrumbs", 1)],
+            FoodV2::Steak => vec![("beef", 1), ("butter", 1), ("salt", 1)],
+            FoodV2::Sushi => vec![("rice", 2), ("fish", 1), ("nori", 1)],
         }
     }
-    // 我们拥有全部食物的食谱，除了法国的蓝带猪排(Cordon Bleu)
-    fn have_recipe(food: FoodV2) -> Option<FoodV2> {
-        match food {
-            FoodV2::CordonBleu => None,
-            _ => Some(food),
+    // 缺货明细：缺的是哪样、要多少、现在能用的有多少
+    #[derive(Debug, Clone, PartialEq)]
+    struct Shortage {
+        ingredient: Ingredient,
+        needed: u32,
+        available: u32,
+    }
+    impl fmt::Display for Shortage {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{} short by {} (need {}, have {})",
+                   self.ingredient, self.needed - self.available, self.needed, self.available)
+        }
+    }
+    #[derive(Debug, Clone, Default)]
+    struct Inventory {
+        stock: HashMap<Ingredient, u32>,
+        reserved: HashMap<Ingredient, u32>,
+        // 每道菜还有几份预留没用掉，consume/release 只能动已经预留过的份数
+        reserved_dishes: HashMap<FoodV2, u32>,
+        // 登记了进货日期和保质期的批次（见后面的“保质期”一节）
+        lots: Vec<Lot>,
+    }
+    impl Inventory {
+        fn add(&mut self, ingredient: Ingredient, quantity: u32) {
+            *self.stock.entry(ingredient).or_insert(0) += quantity;
+        }
+        // 能用的数量 = 库存 - 已预留，预留比库存多（比如预留的料过期被扔了）时算 0
+        fn available(&self, ingredient: Ingredient) -> u32 {
+            let stock = self.stock.get(ingredient).copied().unwrap_or(0);
+            let reserved = self.reserved.get(ingredient).copied().unwrap_or(0);
+            stock.saturating_sub(reserved)
+        }
+        fn shortages(&self, food: FoodV2) -> Vec<Shortage> {
+            bill_of_materials(food)
+                .into_iter()
+                .filter_map(|(ingredient, needed)| {
+                    let available = self.available(ingredient);
+                    if available < needed {
+                        Some(Shortage { ingredient, needed, available })
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        }
+        // 先把一道菜要用的原材料预留下来，缺货的话什么都不动，直接把缺货明细返回
+        fn reserve(&mut self, food: FoodV2) -> Result<(), Vec<Shortage>> {
+            let shortages = self.shortages(food);
+            if !shortages.is_empty() {
+                return Err(shortages);
+            }
+            for (ingredient, quantity) in bill_of_materials(food) {
+                *self.reserved.entry(ingredient).or_insert(0) += quantity;
+            }
+            *self.reserved_dishes.entry(food).or_insert(0) += 1;
+            Ok(())
+        }
+        // 划掉这道菜的一份预留；没有预留过就报错
+        fn take_reservation(&mut self, food: FoodV2) -> Result<(), NotReserved> {
+            match self.reserved_dishes.get_mut(&food) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => return Err(NotReserved(food)),
+            }
+            for (ingredient, quantity) in bill_of_materials(food) {
+                if let Some(reserved) = self.reserved.get_mut(ingredient) {
+                    *reserved = reserved.saturating_sub(quantity);
+                }
+            }
+            Ok(())
+        }
+        // 不做了，把预留的还回去
+        fn release(&mut self, food: FoodV2) -> Result<(), NotReserved> {
+            self.take_reservation(food)
+        }
+        // 真正下锅：把之前预留的那一份从库存里扣掉，没预留过的菜不能直接动库存。
+        // 登记过批次的原材料先用最早进货的那批
+        fn consume(&mut self, food: FoodV2) -> Result<(), NotReserved> {
+            self.take_reservation(food)?;
+            for (ingredient, quantity) in bill_of_materials(food) {
+                if let Some(stock) = self.stock.get_mut(ingredient) {
+                    *stock = stock.saturating_sub(quantity);
+                }
+                self.take_from_lots(ingredient, quantity);
+            }
+            Ok(())
+        }
+    }
+    #[derive(Debug, PartialEq)]
+    struct NotReserved(FoodV2);
+    impl fmt::Display for NotReserved {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{:?} has no outstanding reservation", self.0)
+        }
+    }
+    impl error::Error for NotReserved {}
+
+    // 原材料够不够，现在去查库存，而不是写死“没有寿司的原材料”
+    fn have_ingredients(food: FoodV2, inventory: &Inventory) -> Option<FoodV2> {
+        if inventory.shortages(food).is_empty() {
+            Some(food)
+        } else {
+            None
+        }
+    }
+    // 食谱书：每道菜一张食谱卡，写着菜名、按顺序的步骤和需要的原材料，运行时可以增删
+    #[derive(Debug, Clone)]
+    struct RecipeCard {
+        name: String,
+        food: FoodV2,
+        steps: Vec<String>,
+    }
+    impl RecipeCard {
+        fn new(name: &str, food: FoodV2, steps: &[&str]) -> RecipeCard {
+            RecipeCard {
+                name: name.to_string(),
+                food,
+                steps: steps.iter().map(|step| step.to_string()).collect(),
+            }
+        }
+        // 原材料不单独抄一份，直接读这道菜的物料清单，和库存、购物清单用的是同一份数据
+        fn ingredients(&self) -> Vec<(Ingredient, u32)> {
+            bill_of_materials(self.food)
+        }
+    }
+    #[derive(Debug, Default)]
+    struct RecipeBook {
+        recipes: HashMap<FoodV2, RecipeCard>,
+    }
+    impl RecipeBook {
+        // 同一道菜再加一次会替换旧的食谱，旧的那张返回给调用者
+        fn add(&mut self, food: FoodV2, card: RecipeCard) -> Option<RecipeCard> {
+            self.recipes.insert(food, card)
+        }
+        fn remove(&mut self, food: FoodV2) -> Option<RecipeCard> {
+            self.recipes.remove(&food)
         }
+        fn get(&self, food: FoodV2) -> Option<&RecipeCard> {
+            self.recipes.get(&food)
+        }
+    }
+    // 有没有食谱，去食谱书里查
+    fn have_recipe(food: FoodV2, book: &RecipeBook) -> Option<FoodV2> {
+        book.get(food).map(|_| food)
     }
     // 要做一份好菜，我们需要原材料和食谱
     // 我们可以借助一系列的 match 来表达这个逻辑：
-    fn cookable_v1(food: FoodV2) -> Option<FoodV2> {
-        match have_ingredients(food) {
+    #[allow(clippy::manual_map)]
+    fn cookable_v1(food: FoodV2, inventory: &Inventory, book: &RecipeBook) -> Option<FoodV2> {
+        match have_ingredients(food, inventory) {
             None => None,
-            Some(food) => match have_recipe(food) {
+            Some(food) => match have_recipe(food, book) {
                 None => None,
                 Some(food) => Some(food),
             }
@@ -219,433 +707,2751 @@ fn main() {
     }
 
     // 也可以用 and_then() 把上面的逻辑改写得更紧凑
-    fn cookable_v2(food: FoodV2) -> Option<FoodV2> {
-        have_ingredients(food).and_then(have_recipe)
+    fn cookable_v2(food: FoodV2, inventory: &Inventory, book: &RecipeBook) -> Option<FoodV2> {
+        have_ingredients(food, inventory).and_then(|food| have_recipe(food, book))
+    }
+    // cookable_v1/cookable_v2 只给一个 None，看不出是缺原材料还是缺食谱。
+    // 换成 Result 后，Err 里就能带上具体原因。
+    #[derive(Debug, Clone, PartialEq)]
+    enum CookabilityError {
+        MissingIngredients(FoodV2, Vec<Shortage>),
+        MissingRecipe(FoodV2),
+        // 菜能做，但这位客人不能吃（见后面的“忌口”一节）
+        Restricted(RestrictionError),
+    }
+    impl fmt::Display for CookabilityError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                CookabilityError::MissingIngredients(food, ref shortages) => {
+                    let missing: Vec<String> = shortages.iter().map(|s| s.to_string()).collect();
+                    write!(f, "missing ingredients for {:?}: {}", food, missing.join(", "))
+                }
+                CookabilityError::MissingRecipe(food) => write!(f, "there is no recipe for {:?}", food),
+                CookabilityError::Restricted(ref e) => e.fmt(f),
+            }
+        }
+    }
+    impl error::Error for CookabilityError {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            match *self {
+                CookabilityError::Restricted(ref e) => Some(e),
+                _ => None,
+            }
+        }
     }
-    fn eat_v2(food: FoodV2, day: Day) {
-        match cookable_v2(food) {
-            Some(food) => println!("Yay! On {:?} we get to eat {:?}.", day, food),
-            None => println!("Oh no. We don't get to eat on {:?}?", day),
+    // 检查顺序和 cookable_v2 一样：先原材料，后食谱
+    fn cookable_explained(food: FoodV2, inventory: &Inventory, book: &RecipeBook) -> Result<FoodV2, CookabilityError> {
+        let shortages = inventory.shortages(food);
+        if !shortages.is_empty() {
+            return Err(CookabilityError::MissingIngredients(food, shortages));
         }
+        book.get(food).ok_or(CookabilityError::MissingRecipe(food))?;
+        Ok(food)
+    }
+    fn eat_v2(food: FoodV2, day: Day, inventory: &Inventory, book: &RecipeBook) {
+        match cookable_explained(food, inventory, book) {
+            Ok(food) => println!("Yay! On {:?} we get to eat {:?}.", day, food),
+            Err(e) => println!("Oh no. We don't get to eat on {:?}? {}", day, e),
+        }
+    }
+
+    // 寿司缺米、缺鱼，其他原材料都有
+    let mut inventory = Inventory::default();
+    for ingredient in ["chicken breast", "ham", "cheese", "breadcrumbs", "beef", "butter", "salt", "nori"] {
+        inventory.add(ingredient, 2);
     }
+    inventory.add("rice", 1);
+    // 食谱书里有牛排和寿司，没有蓝带猪排
+    let mut book = RecipeBook::default();
+    book.add(FoodV2::Steak, RecipeCard::new("pan-seared steak", FoodV2::Steak,
+                                            &["season the beef", "sear both sides", "baste with butter", "rest"]));
+    book.add(FoodV2::Sushi, RecipeCard::new("nigiri sushi", FoodV2::Sushi,
+                                            &["cook and season the rice", "slice the fish", "shape and wrap"]));
 
+    // 星期几不再写死，而是从模拟时钟上读，一天吃一道
     let (cordon_bleu, steak, sushi) = (FoodV2::CordonBleu, FoodV2::Steak, FoodV2::Sushi);
-    eat_v2(cordon_bleu, Day::Monday);
-    eat_v2(steak, Day::Tuesday);
-    eat_v2(sushi, Day::Wednesday);
-    //endregion
+    let mut clock = SimClock::starting_at(0, 12);
+    for food in [cordon_bleu, steak, sushi] {
+        eat_v2(food, clock.now().weekday(), &inventory, &book);
+        clock.advance_days(1);
+    }
 
-    //region 18.3.结果Result
-    println!("\n\n=====18.3.结果Resul=====");
-    // Result 是 Option 类型的更丰富的版本，描述的是可能的错误而不是可能的不存在。
-    // 也就是说，Result<T, E> 可以有两个结果的其中一个：
-    //      Ok<T>   ：找到 T 元素
-    //      Err<E>  ：找到 E 元素，E 表示错误的类型
-    // 按照约定，预期结果是 "OK"，而意外结果就是 “Err”。
-    // Result 有很多类似于 Option 的方法。例如： unwrap() ，它要么举出元素 T，要么
-    // 就 panic。
-    // 而对于事件的处理，Result 和 Option 有很多相同的组合算子
-    // parse() 方法返回 Result 类型，并不总是能把字符串解析成指定的类型，也有可能失败
-    fn multiply(first_number_str: &str, second_number_str: &str) -> i32 {
-        let first_number = first_number_str.parse::<i32>().unwrap();
-        let second_number = second_number_str.parse::<i32>().unwrap();
-        first_number * second_number
+    // 运行时补上蓝带猪排的食谱，它就能做了；再删掉又做不成了
+    book.add(FoodV2::CordonBleu, RecipeCard::new("cordon bleu", FoodV2::CordonBleu,
+                                                 &["flatten the chicken", "fill with ham and cheese", "bread", "fry"]));
+    if let Some(card) = book.get(FoodV2::CordonBleu) {
+        println!("{} ({} steps): {}", card.name, card.steps.len(), card.steps.join(" -> "));
     }
-    let twenty = multiply("10", "2");
-    println!("double is {}", twenty);
-    // 这里如果第一个参数为字母的话，只能是引发 panic，退出程序。不友好，看下面的例子就好多了。
-    // 2023年2月3日21时19分24秒
-    let tt = multiply("9","2");
-    println!("double is {}", tt);
-    // 失败的情况下，parse()产生一个错误，留给 unwrap() 来解包并产生 panic。
-    // 另外，panic 会退出程序，并提供一个让人很不爽的错误信息
+    assert_eq!(cookable_v1(FoodV2::CordonBleu, &inventory, &book), Some(FoodV2::CordonBleu));
+    assert_eq!(book.get(FoodV2::Steak).map(|card| card.ingredients()), Some(bill_of_materials(FoodV2::Steak)));
+    assert!(book.remove(FoodV2::CordonBleu).is_some());
+    let standard_chain = CheckChain::standard();
+    let ctx = CookContext { inventory: &inventory, book: &book, day: Day::Monday };
+    assert_eq!(cookable_v2(FoodV2::CordonBleu, &inventory, &book), None);
+    assert_eq!(cookable_checked(FoodV2::CordonBleu, &standard_chain, &ctx), None);
+    assert_eq!(inventory.shortages(FoodV2::Sushi), vec![
+        Shortage { ingredient: "rice", needed: 2, available: 1 },
+        Shortage { ingredient: "fish", needed: 1, available: 0 },
+    ]);
+    assert_eq!(cookable_explained(FoodV2::CordonBleu, &inventory, &book),
+               Err(CookabilityError::MissingRecipe(FoodV2::CordonBleu)));
+
+    // 预留之后别的菜就不能再用这部分原材料了，下锅时再真正扣减
+    inventory.reserve(FoodV2::Steak).expect("steak ingredients are in stock");
+    inventory.reserve(FoodV2::Steak).expect("there is enough for a second steak");
+    assert!(inventory.reserve(FoodV2::Steak).is_err());
+    inventory.release(FoodV2::Steak).expect("two steaks are reserved");
+    inventory.consume(FoodV2::Steak).expect("one steak is still reserved");
+    assert_eq!(inventory.available("beef"), 1);
+    // 两份预留都用掉了，再 consume 或 release 都不行，库存也不会被动到
+    assert_eq!(inventory.consume(FoodV2::Steak), Err(NotReserved(FoodV2::Steak)));
+    assert_eq!(inventory.release(FoodV2::Steak), Err(NotReserved(FoodV2::Steak)));
+    assert_eq!(inventory.consume(FoodV2::CordonBleu), Err(NotReserved(FoodV2::CordonBleu)));
+    assert_eq!(inventory.available("beef"), 1);
     //endregion
 
-    //region 18.3.1.Result的map
-    println!("\n\n=====18.3.1.Result的map=====");
-    // 上一节的 multiply 函数的设计不是健壮的 (robust)，一般地，我们希望把错误返回给调用者
-    // 这样它可以决定回应错误的正确方式
-    // 首先，我们需要了解要处理的错误类型是什么。为了确定 Err 的类型，我们可以用 parse() 来
-    // 实验。Rust 已经为 i32 类型使用 FromStr trait 实现了 parse()。结果表明，
-    // 这里的 Err 类型被指定为 ParseIntError。
-    use std::num::ParseIntError;
-    fn multiply_v1(first_number_str: &str, second_number_str: &str) -> Result<i32, ParseIntError> {
-        match first_number_str.parse::<i32>() {
-            Ok(first_number) => {
-                match second_number_str.parse::<i32>() {
-                    Ok(second_number) => {
-                        Ok(first_number * second_number)
-                    },
-                    Err(e) => Err(e),
+    //region 18.2.3.扩展：一周菜单
+    println!("\n\n=====18.2.3.扩展：一周菜单=====");
+    // eat_v2 一次只看一天一道菜。菜单规划把一周七天、每天几顿饭都排上菜，
+    // 每个格子都用检查链（见后面的“检查链”一节）检查一遍；做不了就按顺序试备选菜，并写明为什么换了菜。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum Meal { Lunch, Dinner }
+    impl Meal {
+        // 几点开饭
+        fn hour(self) -> u32 {
+            match self {
+                Meal::Lunch => 12,
+                Meal::Dinner => 19,
+            }
+        }
+    }
+    #[derive(Debug)]
+    struct PlanSlot {
+        at: SimTime,
+        meal: Meal,
+        wanted: FoodV2,
+        served: Option<FoodV2>,
+        // 换菜（或者没饭吃）的原因，照原计划做的就是 None
+        reason: Option<String>,
+    }
+    struct WeeklyPlan {
+        slots: Vec<PlanSlot>,
+    }
+    impl fmt::Display for WeeklyPlan {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            for slot in &self.slots {
+                let served = match slot.served {
+                    Some(food) => format!("{:?}", food),
+                    None => "nothing".to_string(),
+                };
+                write!(f, "{:<24} {:<6} {}", slot.at.to_string(), format!("{:?}", slot.meal), served)?;
+                match slot.reason {
+                    Some(ref reason) => writeln!(f, " (instead of {:?}: {})", slot.wanted, reason)?,
+                    None => writeln!(f)?,
                 }
-            },
-            Err(e) => Err(e),
+            }
+            Ok(())
         }
     }
-
-    fn print_v1(result: Result<i32, ParseIntError>) {
-        match result {
-            Ok(n) => println!("n is {}", n),
-            Err(e) => println!("Error: {}", e),
+    #[derive(Default)]
+    struct MealPlanner {
+        assignments: HashMap<(Day, Meal), FoodV2>,
+        fallbacks: Vec<FoodV2>,
+    }
+    impl MealPlanner {
+        fn assign(&mut self, day: Day, meal: Meal, food: FoodV2) {
+            self.assignments.insert((day, meal), food);
+        }
+        // 检查链通过、而且剩下的库存还能预留出这一份，这道菜才算排得上
+        fn take(chain: &CheckChain, stock: &mut Inventory, book: &RecipeBook, day: Day, food: FoodV2) -> Result<FoodV2, String> {
+            let checked = {
+                let ctx = CookContext { inventory: stock, book, day };
+                chain.run(food, &ctx)
+            };
+            checked.map_err(|failure| failure.to_string()).and_then(|food| {
+                stock.reserve(food).map(|_| food).map_err(|shortages| {
+                    CookabilityError::MissingIngredients(food, shortages).to_string()
+                })
+            })
+        }
+        // 从时钟所在的那一天开始往后排七天。
+        // 在库存的副本上排：前面排上的菜先把原材料预留掉，后面的格子只能用剩下的
+        fn plan(&self, chain: &CheckChain, inventory: &Inventory, book: &RecipeBook, clock: &SimClock) -> WeeklyPlan {
+            let mut stock = inventory.clone();
+            let mut slots = Vec::new();
+            let first_day = clock.now().day();
+            for day_number in first_day..first_day + 7 {
+                let day = SimTime::at(day_number, 0).weekday();
+                for meal in [Meal::Lunch, Meal::Dinner] {
+                    let at = SimTime::at(day_number, meal.hour());
+                    let wanted = match self.assignments.get(&(day, meal)) {
+                        Some(&food) => food,
+                        None => continue,
+                    };
+                    let reason = match MealPlanner::take(chain, &mut stock, book, day, wanted) {
+                        Ok(_) => {
+                            slots.push(PlanSlot { at, meal, wanted, served: Some(wanted), reason: None });
+                            continue;
+                        }
+                        Err(reason) => reason,
+                    };
+                    let served = self
+                        .fallbacks
+                        .iter()
+                        .copied()
+                        .find(|&fallback| MealPlanner::take(chain, &mut stock, book, day, fallback).is_ok());
+                    slots.push(PlanSlot { at, meal, wanted, served, reason: Some(reason) });
+                }
+            }
+            WeeklyPlan { slots }
         }
     }
 
-    let twenty = multiply_v1("10", "2");
-    print_v1(twenty);
-    let tt = multiply_v1("t", "2");
-    print_v1(tt);
+    let mut planner = MealPlanner { fallbacks: vec![FoodV2::Sushi, FoodV2::Steak], ..Default::default() };
+    let menu = [FoodV2::Steak, FoodV2::Sushi, FoodV2::CordonBleu];
+    for (i, day) in Day::ALL.into_iter().enumerate() {
+        planner.assign(day, Meal::Lunch, menu[i % menu.len()]);
+        planner.assign(day, Meal::Dinner, menu[(i + 1) % menu.len()]);
+    }
+    // 为这一周再进点货，从星期三开始排，排到下周二
+    let mut week_stock = inventory.clone();
+    for ingredient in ["beef", "butter", "salt"] {
+        week_stock.add(ingredient, 4);
+    }
+    week_stock.add("rice", 3);
+    week_stock.add("fish", 2);
+    let weekly_plan = planner.plan(&standard_chain, &week_stock, &book, &SimClock::starting_at(2, 9));
+    print!("{}", weekly_plan);
+    assert_eq!(weekly_plan.slots.len(), 14);
+    assert_eq!(weekly_plan.slots[0].at, SimTime::at(2, 12));
+    assert_eq!(weekly_plan.slots[13].at.weekday(), Day::Tuesday);
+    // 五份牛排、两份寿司的料用完以后，后面的格子就排不上了
+    let served = |food| weekly_plan.slots.iter().filter(|slot| slot.served == Some(food)).count();
+    assert_eq!((served(FoodV2::Steak), served(FoodV2::Sushi)), (5, 2));
+    assert_eq!(weekly_plan.slots[13].served, None);
+    // 排菜单只是在副本上预留，真正的库存没动
+    assert_eq!(week_stock.available("beef"), 5);
     //endregion
 
-    //region 18.3.2.给Result取别名
-    println!("\n\n=====18.3.2.给Result取别名=====");
-    // 当我们要重用某个 Result 类型时，可以创建别名。
-    // 若某个 Result 有可能被重用，可以方便地给它取一个别名。
-    // 在模块层面上创建别名特别有用。同一模块中的错误常常会有相同的 Err 类型，所以单个别名就能简便定义所有相关
-    // 的 Result 。这太有用了，以至于标准库也提供了一个别名： io::Result
-    // use std::num::ParseIntError;  前面已经定义过了！！！ 2023年2月3日23时57分49秒
-    // 为带有错误类型的 'ParseIntError` 的 'Result` 定义一个泛型别名。
-    type AliasedResult<T> = Result<T, ParseIntError>;
-    // 使用上面定义过的别名来表示上一节中的 `Result<i32, ParseIntError>` 类型
-    fn multiply_v2(first_number_str: &str, second_number_str: &str) -> AliasedResult<i32> {
-        first_number_str.parse::<i32>().and_then(|first_number| {
-            second_number_str.parse::<i32>().map(|second_number| first_number * second_number)
-        })
+    //region 18.2.3.扩展：收集全部问题
+    println!("\n\n=====18.2.3.扩展：收集全部问题=====");
+    // and_then 会短路：原材料不够就直接返回，后面的食谱根本不会去查。
+    // 有时候我们想一次把问题全列出来，这就要把每一项检查都跑一遍，把所有失败收集起来。
+    // 失败列表用 NonEmpty 表示，类型上就保证 Err 里至少有一个错误。
+    #[derive(Debug, PartialEq)]
+    struct NonEmpty<T> {
+        first: T,
+        rest: Vec<T>,
     }
-
-    fn print_v2(result: AliasedResult<i32>) {
-        match result {
-            Ok(n) => println!("n is {}", n),
-            Err(e) => println!("Error: {}", e),
+    impl<T> NonEmpty<T> {
+        fn from_vec(mut items: Vec<T>) -> Option<NonEmpty<T>> {
+            if items.is_empty() {
+                return None;
+            }
+            let first = items.remove(0);
+            Some(NonEmpty { first, rest: items })
+        }
+        fn len(&self) -> usize {
+            1 + self.rest.len()
+        }
+        fn iter(&self) -> impl Iterator<Item = &T> {
+            std::iter::once(&self.first).chain(self.rest.iter())
+        }
+    }
+    // 通用的校验：每个检查都跑，全部通过才把值原样交回去
+    type Check<'a, T, E> = &'a dyn Fn(&T) -> Result<(), E>;
+    fn validate_all<T, E>(value: T, checks: &[Check<T, E>]) -> Result<T, NonEmpty<E>> {
+        let errors: Vec<E> = checks.iter().filter_map(|check| check(&value).err()).collect();
+        match NonEmpty::from_vec(errors) {
+            Some(errors) => Err(errors),
+            None => Ok(value),
         }
     }
 
-    print_v2(multiply_v2("10", "2"));
-    print_v2(multiply_v2("r", "2"));
-    //endregion
-
-    //region 18.3.3.提前返回
-    println!("\n\n=====18.3.3.提前返回=====");
-    // 在上一个例子中，显式地使用组合算子处理了错误。
-    // 另一种处理错误的方式是使用 match 语句和提前返回(early return)的结合。
-    // 也就是说，如果发生错误，可以停止函数的执行然后返回错误。
-    fn multiply_v3(first_number_str: &str, second_number_str: &str) -> Result<i32, ParseIntError> {
-        let first_number = match first_number_str.parse::<i32>() {
-            Ok(first_number) => first_number,
-            Err(e) => return Err(e),
+    fn validate_cookable(food: FoodV2, inventory: &Inventory, book: &RecipeBook)
+                         -> Result<FoodV2, NonEmpty<CookabilityError>> {
+        let ingredients = |food: &FoodV2| {
+            let shortages = inventory.shortages(*food);
+            if shortages.is_empty() {
+                Ok(())
+            } else {
+                Err(CookabilityError::MissingIngredients(*food, shortages))
+            }
         };
-        let second_number = match second_number_str.parse::<i32>() {
-            Ok(second_number) => second_number,
-            Err(e) => return Err(e),
+        let recipe = |food: &FoodV2| {
+            book.get(*food).map(|_| ()).ok_or(CookabilityError::MissingRecipe(*food))
         };
-        Ok(first_number * second_number)
+        validate_all(food, &[&ingredients, &recipe])
     }
-    fn print_v3(result: Result<i32, ParseIntError>) {
-        match result {
-            Ok(n) => println!("n is {}", n),
-            Err(e) => println!("Error: {}", e),
+
+    // 削皮、切块、烹饪三道工序也一样：没有食物、缺了哪件厨具，一次全部报出来
+    #[derive(Debug, PartialEq)]
+    enum PipelineIssue {
+        NoFood,
+        MissingTool(Stage, &'static str),
+    }
+    impl fmt::Display for PipelineIssue {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                PipelineIssue::NoFood => write!(f, "there is no food to prepare"),
+                PipelineIssue::MissingTool(stage, tool) => write!(f, "{:?} stage needs a {}", stage, tool),
+            }
         }
     }
-    print_v3(multiply_v3("10", "2"));
-    print_v3(multiply_v3("z", "2"));
-    // 处理错误的一般原则是，要避免 panic 情况的出现，但显式处理所有错误确实是显得过于繁琐。
-    //endregion
+    fn validate_pipeline(food: Option<Food>, tools: &[&str]) -> Result<Food, NonEmpty<PipelineIssue>> {
+        let has_tool = |stage: Stage, tool: &'static str| {
+            move |_: &Option<Food>| {
+                if tools.contains(&tool) { Ok(()) } else { Err(PipelineIssue::MissingTool(stage, tool)) }
+            }
+        };
+        let present = |food: &Option<Food>| food.map(|_| ()).ok_or(PipelineIssue::NoFood);
+        let peeler = has_tool(Stage::Peeled, "peeler");
+        let knife = has_tool(Stage::Chopped, "knife");
+        let stove = has_tool(Stage::Cooked, "stove");
+        validate_all(food, &[&present, &peeler, &knife, &stove])
+            .map(|food| food.expect("checked by `present`"))
+    }
 
-    //region 18.3.4.引入？
-    println!("\n\n=====18.3.4.引入？=====");
-    // 有时我们只是想 unwrap 且产生 panic。到现在为止，对 unwrap 的错误处理都在强迫我们一层层地嵌套，然而我们
-    // 只是想把的变量拿出来，而 ？ 正是为这种情况准备的。
-    // 当找到一个 Err 时，可以采取两种行动：
-    //      1、panic!，不过我们已经决定要尽可能避免 panic
-    //      2、返回它，因为 Err 就意味着它已经不能被处理了
-    // ？ 几乎就等于一个会返回 Err 而不是 panic 的 unwrap！
-    fn multiply_v4(first_number_str: &str, second_number_str: &str) -> Result<i32, ParseIntError> {
-        let first_number = first_number_str.parse::<i32>()?;
-        let second_number = second_number_str.parse::<i32>()?;
-        Ok(first_number * second_number)
+    // 这份库存里没有火腿，食谱书里也没有蓝带猪排，两个问题都要报出来
+    let mut short_inventory = Inventory::default();
+    for ingredient in ["chicken breast", "cheese", "breadcrumbs"] {
+        short_inventory.add(ingredient, 1);
     }
-    fn print_v4(result: Result<i32, ParseIntError>) {
-        match result {
-            Ok(n) => println!("n is {}", n),
-            Err(e) => println!("Error: {}", e),
+    match validate_cookable(FoodV2::CordonBleu, &short_inventory, &book) {
+        Ok(food) => println!("{:?} is cookable", food),
+        Err(errors) => {
+            println!("{} problem(s) with CordonBleu:", errors.len());
+            for e in errors.iter() {
+                println!("    {}", e);
+            }
         }
     }
-    print_v4(multiply_v4("10", "2"));
-    print_v4(multiply_v4("z", "2"));
-    //endregion
+    assert_eq!(validate_cookable(FoodV2::CordonBleu, &short_inventory, &book).unwrap_err().len(), 2);
+    assert_eq!(validate_cookable(FoodV2::Steak, &inventory, &book), Ok(FoodV2::Steak));
 
-    //region 18.4.处理多种错误类型
-    println!("\n\n=====18.4.处理多种错误类型=====");
-    // 前面出现的例子都是很方便的情况，都是 Result 和其他 Result 交互，还有 Optiont 和其他 Option 交互
-    // 有时 Option 需要和 Result 进行交互，或者 Result<T, Error1> 和 Result<T, Error2> 进行交互。
-    // 在下面的代码中，unwrap 的两个实例生成了不同的错误类型。
-    //      Vec::first 返回一个 Option
-    //      parse::<i32> 返回一个 Result<i32, ParseIntError>
-    fn double_first(vec: Vec<&str>) -> i32 {
-        let first = vec.first().unwrap();
-        2 * first.parse::<i32>().unwrap()
+    match validate_pipeline(None, &["peeler"]) {
+        Ok(food) => println!("ready to prepare {:?}", food),
+        Err(issues) => {
+            for issue in issues.iter() {
+                println!("    {}", issue);
+            }
+        }
     }
-    let numbers = vec!["42", "93", "18"];
-    // let empty = vec![];
-    // let strings = vec!["tofu", "93", "18"];
-    println!("The first doubled is {}", double_first(numbers));
-    // println!("The first doubled is {}", double_first(empty));
-    // println!("The first doubled is {}", double_first(strings));
+    assert_eq!(validate_pipeline(None, &["peeler"]).unwrap_err().len(), 3);
+    assert_eq!(validate_pipeline(Some(Food::Carrot), &["peeler", "knife", "stove"]), Ok(Food::Carrot));
     //endregion
 
-    //region 18.4.1.从Option中取出Result
-    println!("\n\n=====18.4.1.从Option中取出Result=====");
-    // 处理混合错误类型的最基本的手段就是让它们互相包含。
-    fn double_first_v1(vec: Vec<&str>) -> Option<Result<i32, ParseIntError>> {
-        vec.first().map(|first| {
-            first.parse::<i32>().map(|n| 2 * n)
+    //region 18.2.3.扩展：检查链
+    println!("\n\n=====18.2.3.扩展：检查链=====");
+    // cookable_v2 原来固定只查原材料和食谱两项。实际上还要看厨具在不在、当天有没有厨师、
+    // 有没有忌口、这道菜今天供不供应……这里把每一项检查抽象成 CookCheck trait，
+    // 用 CheckChain 按注册顺序逐个执行，运行时可以继续往里加检查；哪一项没通过，失败信息里就带上它的名字。
+    // 检查时需要的共享状态（库存、食谱书、今天星期几）放在 CookContext 里传进去。
+    struct CookContext<'a> {
+        inventory: &'a Inventory,
+        book: &'a RecipeBook,
+        day: Day,
+    }
+    // 每项检查失败时返回它自己的错误类型，装箱后原样带出来，调用者可以 downcast 拿到细节
+    type CheckResult = Result<(), Box<dyn error::Error>>;
+    #[derive(Debug)]
+    struct CheckFailure {
+        check: String,
+        reason: Box<dyn error::Error>,
+    }
+    impl fmt::Display for CheckFailure {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "[{}] {}", self.check, self.reason)
+        }
+    }
+    impl error::Error for CheckFailure {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            Some(self.reason.as_ref())
+        }
+    }
+    // 厨具、厨师、供应日这几项检查的错误
+    #[derive(Debug, Clone, PartialEq)]
+    enum KitchenError {
+        MissingEquipment(FoodV2, &'static str),
+        NoChefOnDuty(Day),
+        NotServedToday(FoodV2, Vec<Day>),
+    }
+    impl fmt::Display for KitchenError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                KitchenError::MissingEquipment(food, equipment) => write!(f, "{:?} needs a {}", food, equipment),
+                KitchenError::NoChefOnDuty(day) => write!(f, "nobody is cooking on {:?}", day),
+                KitchenError::NotServedToday(food, ref days) => write!(f, "{:?} is only served on {:?}", food, days),
+            }
+        }
+    }
+    impl error::Error for KitchenError {}
+    trait CookCheck {
+        fn name(&self) -> &str;
+        fn check(&self, food: FoodV2, ctx: &CookContext) -> CheckResult;
+    }
+    #[derive(Default)]
+    struct CheckChain {
+        checks: Vec<Box<dyn CookCheck>>,
+    }
+    impl CheckChain {
+        // 和原来的 cookable_v2 一样：先原材料，后食谱
+        fn standard() -> CheckChain {
+            let mut chain = CheckChain::default();
+            chain.register(IngredientsCheck);
+            chain.register(RecipeCheck);
+            chain
+        }
+        fn register<C: CookCheck + 'static>(&mut self, check: C) -> &mut CheckChain {
+            self.checks.push(Box::new(check));
+            self
+        }
+        fn names(&self) -> Vec<&str> {
+            self.checks.iter().map(|check| check.name()).collect()
+        }
+        // 相当于把 and_then 一路串下去：遇到第一个失败就停下
+        fn run(&self, food: FoodV2, ctx: &CookContext) -> Result<FoodV2, CheckFailure> {
+            self.checks.iter().try_fold(food, |food, check| {
+                check
+                    .check(food, ctx)
+                    .map(|_| food)
+                    .map_err(|reason| CheckFailure { check: check.name().to_string(), reason })
+            })
+        }
+    }
+    // cookable_v2 的检查链版本：检查项由 chain 决定，只关心能不能做的时候把失败信息丢掉
+    fn cookable_checked(food: FoodV2, chain: &CheckChain, ctx: &CookContext) -> Option<FoodV2> {
+        chain.run(food, ctx).ok()
+    }
+
+    struct IngredientsCheck;
+    impl CookCheck for IngredientsCheck {
+        fn name(&self) -> &str {
+            "ingredients"
+        }
+        fn check(&self, food: FoodV2, ctx: &CookContext) -> CheckResult {
+            match have_ingredients(food, ctx.inventory) {
+                Some(_) => Ok(()),
+                None => Err(Box::new(CookabilityError::MissingIngredients(food, ctx.inventory.shortages(food)))),
+            }
+        }
+    }
+    struct RecipeCheck;
+    impl CookCheck for RecipeCheck {
+        fn name(&self) -> &str {
+            "recipe"
+        }
+        fn check(&self, food: FoodV2, ctx: &CookContext) -> CheckResult {
+            match have_recipe(food, ctx.book) {
+                Some(_) => Ok(()),
+                None => Err(Box::new(CookabilityError::MissingRecipe(food))),
+            }
+        }
+    }
+    // 每道菜要用的厨具
+    fn equipment_for(food: FoodV2) -> &'static str {
+        match food {
+            FoodV2::CordonBleu => "deep fryer",
+            FoodV2::Steak => "grill pan",
+            FoodV2::Sushi => "rice cooker",
+        }
+    }
+    struct EquipmentCheck {
+        available: Vec<&'static str>,
+    }
+    impl CookCheck for EquipmentCheck {
+        fn name(&self) -> &str {
+            "equipment"
+        }
+        fn check(&self, food: FoodV2, _ctx: &CookContext) -> CheckResult {
+            let needed = equipment_for(food);
+            if self.available.contains(&needed) {
+                Ok(())
+            } else {
+                Err(Box::new(KitchenError::MissingEquipment(food, needed)))
+            }
+        }
+    }
+    struct ChefOnDutyCheck {
+        roster: HashMap<Day, Vec<&'static str>>,
+    }
+    impl CookCheck for ChefOnDutyCheck {
+        fn name(&self) -> &str {
+            "chef on duty"
+        }
+        fn check(&self, _food: FoodV2, ctx: &CookContext) -> CheckResult {
+            match self.roster.get(&ctx.day) {
+                Some(chefs) if !chefs.is_empty() => Ok(()),
+                _ => Err(Box::new(KitchenError::NoChefOnDuty(ctx.day))),
+            }
+        }
+    }
+    // 这一桌客人里只要有一位不能吃，这道菜就不做（客人的忌口见后面的“忌口”一节）
+    struct DietaryCheck {
+        diners: Vec<Diner>,
+    }
+    impl CookCheck for DietaryCheck {
+        fn name(&self) -> &str {
+            "dietary restrictions"
+        }
+        fn check(&self, food: FoodV2, _ctx: &CookContext) -> CheckResult {
+            for diner in &self.diners {
+                check_diner(food, diner)?;
+            }
+            Ok(())
+        }
+    }
+    // 有些菜只在特定的日子供应，没登记的菜每天都有
+    struct DayAvailabilityCheck {
+        only_on: HashMap<FoodV2, Vec<Day>>,
+    }
+    impl CookCheck for DayAvailabilityCheck {
+        fn name(&self) -> &str {
+            "day of week"
+        }
+        fn check(&self, food: FoodV2, ctx: &CookContext) -> CheckResult {
+            match self.only_on.get(&food) {
+                Some(days) if !days.contains(&ctx.day) => Err(Box::new(KitchenError::NotServedToday(food, days.clone()))),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    let mut chain = CheckChain::standard();
+    chain
+        .register(EquipmentCheck { available: vec!["grill pan", "rice cooker"] })
+        .register(ChefOnDutyCheck {
+            roster: Day::ALL.into_iter().filter(|&day| day != Day::Sunday).map(|day| (day, vec!["Ana"])).collect(),
         })
+        .register(DietaryCheck { diners: vec![] })
+        .register(DayAvailabilityCheck { only_on: HashMap::from([(FoodV2::Steak, vec![Day::Friday, Day::Saturday])]) });
+    println!("checks: {}", chain.names().join(" -> "));
+    for day in [Day::Tuesday, Day::Friday, Day::Sunday] {
+        let ctx = CookContext { inventory: &inventory, book: &book, day };
+        match chain.run(FoodV2::Steak, &ctx) {
+            Ok(food) => println!("{:?}: {:?} is cookable", day, food),
+            Err(failure) => println!("{:?}: {}", day, failure),
+        }
+    }
+    let friday = CookContext { inventory: &inventory, book: &book, day: Day::Friday };
+    assert_eq!(cookable_checked(FoodV2::Steak, &chain, &friday), Some(FoodV2::Steak));
+    let failure = chain.run(FoodV2::Sushi, &friday).unwrap_err();
+    assert_eq!(failure.check, "ingredients");
+    // 缺货明细没有被压成字符串，可以从 source() 里原样取回来
+    match error::Error::source(&failure).and_then(|e| e.downcast_ref::<CookabilityError>()) {
+        Some(CookabilityError::MissingIngredients(food, shortages)) => {
+            assert_eq!(*food, FoodV2::Sushi);
+            assert_eq!(shortages.len(), 2);
+        }
+        other => panic!("expected missing ingredients, got {:?}", other),
     }
+    //endregion
 
-    let numbers = vec!["42", "93", "18"];
-    let empty = vec![];
-    let strings = vec!["tofu", "93", "18"];
+    //region 18.2.3.扩展：忌口
+    println!("\n\n=====18.2.3.扩展：忌口=====");
+    // eat_v2 不管是谁来吃，牛排、寿司照上不误。这里给客人建档：吃素、只吃海鲜不吃肉、对什么过敏，
+    // 再给每道菜打上标签。菜和客人对不上时，这道菜对这位客人来说就是“做不了”，并给出明确的原因。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum DishTag { Meat, Pork, Fish, Dairy, Gluten }
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Diet { Vegetarian, Pescatarian }
+    impl Diet {
+        // 这种饮食习惯不吃哪些东西
+        fn forbids(self, tag: DishTag) -> bool {
+            match self {
+                Diet::Vegetarian => matches!(tag, DishTag::Meat | DishTag::Pork | DishTag::Fish),
+                Diet::Pescatarian => matches!(tag, DishTag::Meat | DishTag::Pork),
+            }
+        }
+    }
+    fn dish_tags(food: FoodV2) -> Vec<DishTag> {
+        match food {
+            FoodV2::CordonBleu => vec![DishTag::Meat, DishTag::Pork, DishTag::Dairy, DishTag::Gluten],
+            FoodV2::Steak => vec![DishTag::Meat, DishTag::Dairy],
+            FoodV2::Sushi => vec![DishTag::Fish],
+        }
+    }
+    #[derive(Debug, Clone)]
+    struct Diner {
+        name: String,
+        diets: Vec<Diet>,
+        // 过敏原直接用菜的标签表示，比如 Fish、Dairy、Gluten
+        allergies: Vec<DishTag>,
+    }
+    #[derive(Debug, Clone, PartialEq)]
+    enum Restriction {
+        Diet(Diet, DishTag),
+        Allergy(DishTag),
+    }
+    #[derive(Debug, Clone, PartialEq)]
+    struct RestrictionError {
+        diner: String,
+        food: FoodV2,
+        restriction: Restriction,
+    }
+    impl fmt::Display for RestrictionError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.restriction {
+                Restriction::Diet(diet, tag) =>
+                    write!(f, "{} is {:?} and {:?} contains {:?}", self.diner, diet, self.food, tag),
+                Restriction::Allergy(tag) =>
+                    write!(f, "{} is allergic to {:?}, which {:?} contains", self.diner, tag, self.food),
+            }
+        }
+    }
+    impl error::Error for RestrictionError {}
+    // 过敏比饮食习惯更要紧，所以先查过敏
+    fn check_diner(food: FoodV2, diner: &Diner) -> Result<FoodV2, RestrictionError> {
+        let tags = dish_tags(food);
+        let restricted = |restriction| RestrictionError { diner: diner.name.clone(), food, restriction };
+        if let Some(&tag) = tags.iter().find(|tag| diner.allergies.contains(tag)) {
+            return Err(restricted(Restriction::Allergy(tag)));
+        }
+        for &diet in &diner.diets {
+            if let Some(&tag) = tags.iter().find(|&&tag| diet.forbids(tag)) {
+                return Err(restricted(Restriction::Diet(diet, tag)));
+            }
+        }
+        Ok(food)
+    }
+    // 先看厨房能不能做，再看这位客人能不能吃
+    fn cookable_for(food: FoodV2, diner: &Diner, inventory: &Inventory, book: &RecipeBook)
+                    -> Result<FoodV2, CookabilityError> {
+        cookable_explained(food, inventory, book)
+            .and_then(|food| check_diner(food, diner).map_err(CookabilityError::Restricted))
+    }
+    fn eat_for(food: FoodV2, diner: &Diner, day: Day, inventory: &Inventory, book: &RecipeBook) {
+        match cookable_for(food, diner, inventory, book) {
+            Ok(food) => println!("Yay! On {:?} {} gets to eat {:?}.", day, diner.name, food),
+            Err(e) => println!("Oh no. {} doesn't get to eat {:?} on {:?}? {}", diner.name, food, day, e),
+        }
+    }
 
-    println!("the first doubled is {:?}", double_first_v1(numbers));
-    println!("the first doubled is {:?}", double_first_v1(empty));
-    println!("the first doubled is {:?}", double_first_v1(strings));
+    let vera = Diner { name: "Vera".to_string(), diets: vec![Diet::Vegetarian], allergies: vec![] };
+    let finn = Diner { name: "Finn".to_string(), diets: vec![], allergies: vec![DishTag::Fish] };
+    let pia = Diner { name: "Pia".to_string(), diets: vec![Diet::Pescatarian], allergies: vec![DishTag::Dairy] };
+    eat_for(FoodV2::Steak, &vera, Day::Tuesday, &inventory, &book);
+    eat_for(FoodV2::Steak, &finn, Day::Tuesday, &inventory, &book);
+    eat_for(FoodV2::Steak, &pia, Day::Tuesday, &inventory, &book);
+    assert_eq!(check_diner(FoodV2::Sushi, &finn).unwrap_err().restriction, Restriction::Allergy(DishTag::Fish));
+    assert_eq!(check_diner(FoodV2::Sushi, &pia), Ok(FoodV2::Sushi));
 
-    // 有时候我们不想再处理错误（比如使用 ？ 的时候），但如果 Option 是 None 则 继续处理错误
-    // 一些组合算子可以让我们轻松地交换 Result 和 Option。
-    println!("\n使用组合可以让我们轻松地交换 Result 和 Option。");
-    fn double_first_v2(vec: Vec<&str>) -> Result<Option<i32>, ParseIntError> {
-        let opt = vec.first().map(|first| {
-            first.parse::<i32>().map(|n| 2 * n)
-        });
-        opt.map_or(Ok(None), |r| r.map(Some))
+    // 放进检查链里，整桌客人一起考虑
+    let mut table_chain = CheckChain::standard();
+    table_chain.register(DietaryCheck { diners: vec![finn.clone(), vera.clone()] });
+    let ctx = CookContext { inventory: &inventory, book: &book, day: Day::Tuesday };
+    if let Err(failure) = table_chain.run(FoodV2::Steak, &ctx) {
+        println!("{}", failure);
     }
+    assert_eq!(cookable_checked(FoodV2::Steak, &table_chain, &ctx), None);
+    //endregion
 
-    let numbers = vec!["42", "93", "18"];
-    let empty = vec![];
-    let strings = vec!["tofu", "93", "18"];
+    //region 18.2.3.扩展：点单队列
+    println!("\n\n=====18.2.3.扩展：点单队列=====");
+    // eat_v2 一次只处理一道菜，而且是同步的。餐厅里是很多客人陆续下单，
+    // 后厨按先来后到（或者按优先级）一单一单地做：过检查链、从库存里预留并扣减原材料。
+    // 每一单最后不是上菜(served)、就是被拒(rejected)、或者被客人取消(cancelled)，全过程都记日志并带时间戳。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    enum Priority { Normal, Vip }
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum QueueMode { Fifo, ByPriority }
+    #[derive(Debug)]
+    struct Order {
+        id: u32,
+        customer: String,
+        dish: FoodV2,
+        priority: Priority,
+        submitted_at: SimTime,
+    }
+    #[derive(Debug, Clone, PartialEq)]
+    enum OrderStatus {
+        Served,
+        Rejected(String),
+        Cancelled,
+    }
+    #[derive(Debug)]
+    struct OrderLogEntry {
+        at: SimTime,
+        order_id: u32,
+        message: String,
+    }
+    // 每道菜要做几分钟
+    fn cook_minutes(food: FoodV2) -> u32 {
+        match food {
+            FoodV2::CordonBleu => 25,
+            FoodV2::Steak => 15,
+            FoodV2::Sushi => 10,
+        }
+    }
+    struct OrderQueue {
+        mode: QueueMode,
+        clock: SimClock,
+        next_id: u32,
+        pending: Vec<Order>,
+        outcomes: HashMap<u32, OrderStatus>,
+        log: Vec<OrderLogEntry>,
+    }
+    impl OrderQueue {
+        fn new(mode: QueueMode, clock: SimClock) -> OrderQueue {
+            OrderQueue { mode, clock, next_id: 1, pending: Vec::new(), outcomes: HashMap::new(), log: Vec::new() }
+        }
+        fn record(&mut self, order_id: u32, message: String) {
+            self.log.push(OrderLogEntry { at: self.clock.now(), order_id, message });
+        }
+        fn wait(&mut self, minutes: u32) {
+            self.clock.advance_minutes(minutes);
+        }
+        fn submit(&mut self, customer: &str, dish: FoodV2, priority: Priority) -> u32 {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.pending.push(Order { id, customer: customer.to_string(), dish, priority, submitted_at: self.clock.now() });
+            self.record(id, format!("{} ordered {:?} ({:?})", customer, dish, priority));
+            id
+        }
+        // 只有还没开始做的单才能取消
+        fn cancel(&mut self, id: u32) -> bool {
+            match self.pending.iter().position(|order| order.id == id) {
+                Some(index) => {
+                    let order = self.pending.remove(index);
+                    self.record(id, format!("{} cancelled", order.customer));
+                    self.outcomes.insert(id, OrderStatus::Cancelled);
+                    true
+                }
+                None => false,
+            }
+        }
+        // 先进先出；按优先级时，同一优先级里还是先来先做
+        fn next_order(&mut self) -> Option<Order> {
+            let index = match self.mode {
+                QueueMode::Fifo => (!self.pending.is_empty()).then_some(0),
+                QueueMode::ByPriority => self
+                    .pending
+                    .iter()
+                    .enumerate()
+                    // 同优先级先到先得：时间相同再比 id，id 小的先出
+                    .max_by(|(_, a), (_, b)| {
+                        a.priority
+                            .cmp(&b.priority)
+                            .then(b.submitted_at.cmp(&a.submitted_at))
+                            .then(b.id.cmp(&a.id))
+                    })
+                    .map(|(index, _)| index),
+            };
+            index.map(|index| self.pending.remove(index))
+        }
+        fn process_all(&mut self, chain: &CheckChain, inventory: &mut Inventory, book: &RecipeBook) {
+            while let Some(order) = self.next_order() {
+                let checked = {
+                    let ctx = CookContext { inventory, book, day: self.clock.now().weekday() };
+                    chain.run(order.dish, &ctx)
+                };
+                // 检查通过后还要真的预留到原材料，前面的单可能刚把最后一份用掉
+                let reserved = checked.map_err(|failure| failure.to_string()).and_then(|dish| {
+                    inventory.reserve(dish).map(|_| dish).map_err(|shortages| {
+                        CookabilityError::MissingIngredients(dish, shortages).to_string()
+                    })
+                });
+                let status = match reserved {
+                    Ok(dish) => {
+                        self.record(order.id, format!("cooking {:?} for {}", dish, order.customer));
+                        self.wait(cook_minutes(dish));
+                        inventory.consume(dish).expect("the dish was reserved just above");
+                        self.record(order.id, format!("served {:?} to {}", dish, order.customer));
+                        OrderStatus::Served
+                    }
+                    Err(reason) => {
+                        self.record(order.id, format!("rejected: {}", reason));
+                        OrderStatus::Rejected(reason)
+                    }
+                };
+                self.outcomes.insert(order.id, status);
+            }
+        }
+    }
 
-    println!("the first doubled is {:?}", double_first_v2(numbers));
-    println!("the first doubled is {:?}", double_first_v2(empty));
-    println!("the first doubled is {:?}", double_first_v2(strings));
+    // 只备了两份牛排的料
+    let mut service_inventory = Inventory::default();
+    for ingredient in ["beef", "butter", "salt"] {
+        service_inventory.add(ingredient, 2);
+    }
+    // 星期五晚上六点开门
+    let mut queue = OrderQueue::new(QueueMode::ByPriority, SimClock::starting_at(4, 18));
+    let first = queue.submit("Ann", FoodV2::Steak, Priority::Normal);
+    queue.wait(2);
+    let second = queue.submit("Bob", FoodV2::Sushi, Priority::Normal);
+    let third = queue.submit("Cleo", FoodV2::Steak, Priority::Vip);
+    queue.wait(1);
+    let fourth = queue.submit("Dan", FoodV2::Steak, Priority::Normal);
+    let fifth = queue.submit("Eve", FoodV2::Steak, Priority::Normal);
+    queue.cancel(fifth);
+    queue.process_all(&standard_chain, &mut service_inventory, &book);
+    for entry in &queue.log {
+        println!("[{}] #{} {}", entry.at, entry.order_id, entry.message);
+    }
+    // 3 分钟下单 + 两份牛排各 15 分钟
+    let mut expected = SimClock::starting_at(4, 18);
+    expected.advance_minutes(33);
+    assert_eq!(queue.clock.now(), expected.now());
+    assert_eq!(queue.outcomes[&third], OrderStatus::Served);
+    assert_eq!(queue.outcomes[&first], OrderStatus::Served);
+    assert!(matches!(queue.outcomes[&second], OrderStatus::Rejected(_)));
+    assert!(matches!(queue.outcomes[&fourth], OrderStatus::Rejected(_)));
+    assert_eq!(queue.outcomes[&fifth], OrderStatus::Cancelled);
+    // 同一分钟下的同级单也要先到先得
+    let mut same_minute = OrderQueue::new(QueueMode::ByPriority, SimClock::starting_at(4, 18));
+    let earlier = same_minute.submit("Fay", FoodV2::Steak, Priority::Normal);
+    let later = same_minute.submit("Gus", FoodV2::Steak, Priority::Normal);
+    assert_eq!(same_minute.next_order().map(|order| order.id), Some(earlier));
+    assert_eq!(same_minute.next_order().map(|order| order.id), Some(later));
+    // 同样的单换成 FIFO：不看优先级，VIP 也得排在 Ann 后面
+    let mut fifo_inventory = Inventory::default();
+    for ingredient in ["beef", "butter", "salt"] {
+        fifo_inventory.add(ingredient, 2);
+    }
+    let mut fifo = OrderQueue::new(QueueMode::Fifo, SimClock::starting_at(4, 18));
+    let first = fifo.submit("Ann", FoodV2::Steak, Priority::Normal);
+    let second = fifo.submit("Bob", FoodV2::Steak, Priority::Normal);
+    let third = fifo.submit("Cleo", FoodV2::Steak, Priority::Vip);
+    fifo.process_all(&standard_chain, &mut fifo_inventory, &book);
+    assert_eq!(fifo.outcomes[&first], OrderStatus::Served);
+    assert_eq!(fifo.outcomes[&second], OrderStatus::Served);
+    assert!(matches!(fifo.outcomes[&third], OrderStatus::Rejected(_)));
     //endregion
 
-    //region 18.4.2.定义一个错误类型
-    println!("\n\n=====18.4.2.定义一个错误类型=====");
-    // 有时候把所有不同的错误都视为一种错误类型会简化代码。
-    // 我们将用一个自定义错误类型来演示这一点。
-    // Rust 允许我们定义自己的错误类型，一般来说，一个“好的”错误类型应当：
-    //      用一个类型代表了多种错误
-    //      向用户提供了清楚的错误信息
-    //      能够容易地与其它类型比较
-    //          好的例子：Err(EmptyVec)
-    //          坏的例子：Err("Please use a vector with at least one element".to_owned())
-    //      能够容纳错误的具体信息
-    //          好的例子：Err(BadChar(c, position))
-    //          坏的例子：Err("+ cannot be used here".to_owned())
-    //      能够与其他错误很好地整合
-    use std::error;
-    use std::fmt;
-    // 定义我们的错误类型，这种类型可以根据错误处理的实际情况定制。
-    // 我们可以完全自定义错误类型，也可以在类型中完全采用底层的错误实现，也可以介于二者之间。
+    //region 18.2.2.扩展：保质期
+    println!("\n\n=====18.2.2.扩展：保质期=====");
+    // peel、chop、cook 都不知道食物会坏。这里让食材带上进货日期和保质期，
+    // 每道工序开工前看一眼模拟时钟，过期了就用 SpoiledError 拒绝；
+    // 库存这边按批次记下日期，可以列出快要过期的东西，也可以把已经坏掉的清出去。
     #[derive(Debug)]
-    struct DoubleError;
-    type MyResult<T> = std::result::Result<T, DoubleError>;
+    struct Fresh<T> {
+        item: T,
+        food: Food,
+        acquired_day: u32,
+        shelf_life_days: u32,
+    }
+    impl<T> Fresh<T> {
+        // 从 expires_on 这一天开始就算坏了
+        fn expires_on(&self) -> u32 {
+            self.acquired_day + self.shelf_life_days
+        }
+        fn check(&self, stage: Stage, now: SimTime) -> Result<(), SpoiledError> {
+            if now.day() >= self.expires_on() {
+                Err(SpoiledError { food: self.food, stage, expired_on: self.expires_on(), today: now.day() })
+            } else {
+                Ok(())
+            }
+        }
+        // 加工成下一个阶段，日期跟着走
+        fn then<U>(self, item: U) -> Fresh<U> {
+            Fresh { item, food: self.food, acquired_day: self.acquired_day, shelf_life_days: self.shelf_life_days }
+        }
+    }
+    fn fresh(food: Food, acquired_day: u32, shelf_life_days: u32) -> Fresh<Food> {
+        Fresh { item: food, food, acquired_day, shelf_life_days }
+    }
+    #[derive(Debug, PartialEq)]
+    struct SpoiledError {
+        food: Food,
+        // 在哪道工序上发现的
+        stage: Stage,
+        expired_on: u32,
+        today: u32,
+    }
+    impl fmt::Display for SpoiledError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{:?} went bad on day {} (today is day {}), refusing to make it {:?}",
+                   self.food, self.expired_on, self.today, self.stage)
+        }
+    }
+    impl error::Error for SpoiledError {}
+    fn peel_fresh(food: Fresh<Food>, now: SimTime) -> Result<Fresh<Peeled>, SpoiledError> {
+        food.check(Stage::Peeled, now)?;
+        let item = Peeled(food.item);
+        Ok(food.then(item))
+    }
+    fn chop_fresh(peeled: Fresh<Peeled>, now: SimTime) -> Result<Fresh<Chopped>, SpoiledError> {
+        peeled.check(Stage::Chopped, now)?;
+        let Peeled(food) = peeled.item;
+        Ok(peeled.then(Chopped(food)))
+    }
+    fn cook_fresh(chopped: Fresh<Chopped>, now: SimTime) -> Result<Cooked, SpoiledError> {
+        chopped.check(Stage::Cooked, now)?;
+        let Chopped(food) = chopped.item;
+        Ok(Cooked(food))
+    }
 
-    // 错误的生成与它如何显示是完全没关系的，没有必要担心的逻辑会导致混乱的显示
-    // 注意：我们没有储存关于错误的任何额外信息，也就是说，如果不修改我们的错误类型定义的话，就
-    // 无法指明是哪个字符串解析失败了。
-    impl fmt::Display for DoubleError {
+    #[derive(Debug, Clone, PartialEq)]
+    struct Lot {
+        ingredient: Ingredient,
+        quantity: u32,
+        acquired_day: u32,
+        shelf_life_days: u32,
+    }
+    impl Lot {
+        fn expires_on(&self) -> u32 {
+            self.acquired_day + self.shelf_life_days
+        }
+    }
+    impl Inventory {
+        fn add_lot(&mut self, ingredient: Ingredient, quantity: u32, acquired_day: u32, shelf_life_days: u32) {
+            self.add(ingredient, quantity);
+            self.lots.push(Lot { ingredient, quantity, acquired_day, shelf_life_days });
+        }
+        // 还没坏、但 days 天之内就会坏的批次，最早过期的排在前面
+        fn expiring_within(&self, now: SimTime, days: u32) -> Vec<&Lot> {
+            let today = now.day();
+            let mut lots: Vec<&Lot> = self
+                .lots
+                .iter()
+                .filter(|lot| lot.expires_on() > today && lot.expires_on() <= today + days)
+                .collect();
+            lots.sort_by_key(|lot| lot.expires_on());
+            lots
+        }
+        // 从最早进货的批次开始扣，扣空的批次直接删掉
+        fn take_from_lots(&mut self, ingredient: Ingredient, quantity: u32) {
+            let mut remaining = quantity;
+            let mut order: Vec<usize> = (0..self.lots.len()).filter(|&i| self.lots[i].ingredient == ingredient).collect();
+            order.sort_by_key(|&i| self.lots[i].acquired_day);
+            for i in order {
+                if remaining == 0 {
+                    break;
+                }
+                let taken = remaining.min(self.lots[i].quantity);
+                self.lots[i].quantity -= taken;
+                remaining -= taken;
+            }
+            self.lots.retain(|lot| lot.quantity > 0);
+        }
+        // 把已经坏掉的批次从库存里扣掉并返回。
+        // 扔掉的料里可能有已经预留的：用到这样原材料的菜一份份撤掉预留，直到剩下的库存够用，
+        // 这样之后 consume 这道菜会报 NotReserved，而不是拿已经扔掉的料去做
+        fn discard_expired(&mut self, now: SimTime) -> Vec<Lot> {
+            let today = now.day();
+            let (expired, kept): (Vec<Lot>, Vec<Lot>) =
+                self.lots.drain(..).partition(|lot| lot.expires_on() <= today);
+            self.lots = kept;
+            for lot in &expired {
+                let stock = self.stock.entry(lot.ingredient).or_insert(0);
+                *stock = stock.saturating_sub(lot.quantity);
+                let remaining = *stock;
+                while self.reserved.get(lot.ingredient).copied().unwrap_or(0) > remaining {
+                    let dish = [FoodV2::CordonBleu, FoodV2::Steak, FoodV2::Sushi].into_iter().find(|dish| {
+                        self.reserved_dishes.get(dish).copied().unwrap_or(0) > 0
+                            && bill_of_materials(*dish).iter().any(|&(ingredient, _)| ingredient == lot.ingredient)
+                    });
+                    match dish {
+                        Some(dish) => self.take_reservation(dish).expect("the dish has an outstanding reservation"),
+                        None => break,
+                    }
+                }
+            }
+            expired
+        }
+    }
+
+    let mut clock = SimClock::starting_at(0, 9);
+    let carrot = fresh(Food::Carrot, 0, 2);
+    let apple = fresh(Food::Apple, 0, 2);
+    let cooked_carrot = peel_fresh(carrot, clock.now())
+        .and_then(|peeled| chop_fresh(peeled, clock.now()))
+        .and_then(|chopped| cook_fresh(chopped, clock.now()));
+    println!("{:?}", cooked_carrot);
+    // 苹果削了皮放了两天，再切的时候已经坏了
+    let peeled_apple = peel_fresh(apple, clock.now()).expect("the apple is still fresh in the morning");
+    clock.advance_days(2);
+    let chopped_apple = chop_fresh(peeled_apple, clock.now());
+    let chopped_item = chopped_apple.as_ref().map(|chopped| format!("{:?}", chopped.item));
+    report_result("chopped", chopped_item.as_ref(), ReportStyle::Plain);
+    assert_eq!(chopped_apple.unwrap_err().stage, Stage::Chopped);
+
+    let mut pantry = Inventory::default();
+    pantry.add_lot("fish", 2, 0, 2);
+    pantry.add_lot("rice", 5, 0, 30);
+    pantry.add_lot("butter", 1, 1, 3);
+    pantry.add_lot("beef", 2, 2, 4);
+    pantry.add("nori", 1);
+    // 坏掉的鱼里有一份已经给寿司预留了
+    pantry.reserve(FoodV2::Sushi).expect("the pantry has everything for sushi");
+    println!("expiring within 2 days of {}:", clock.now());
+    for lot in pantry.expiring_within(clock.now(), 2) {
+        println!("    {} x{} (expires on day {})", lot.ingredient, lot.quantity, lot.expires_on());
+    }
+    let discarded = pantry.discard_expired(clock.now());
+    println!("discarded: {:?}", discarded.iter().map(|lot| lot.ingredient).collect::<Vec<_>>());
+    assert_eq!(pantry.expiring_within(clock.now(), 2).len(), 1);
+    assert_eq!(pantry.available("fish"), 0);
+    assert_eq!(pantry.reserved["fish"], 0);
+    // 预留的那份寿司跟着坏掉的鱼一起撤掉了，不能再拿来做
+    assert_eq!(pantry.consume(FoodV2::Sushi), Err(NotReserved(FoodV2::Sushi)));
+
+    // 下锅时先用旧的那批牛肉，之后过期清理就不会再把用掉的扣一遍
+    let mut larder = Inventory::default();
+    larder.add_lot("beef", 1, 0, 2);
+    larder.add_lot("beef", 1, 1, 5);
+    larder.add("butter", 1);
+    larder.add("salt", 1);
+    larder.reserve(FoodV2::Steak).expect("the larder has everything for a steak");
+    larder.consume(FoodV2::Steak).expect("the steak was reserved just above");
+    assert!(larder.discard_expired(clock.now()).is_empty());
+    assert_eq!(larder.available("beef"), 1);
+    //endregion
+
+    //region 18.2.3.扩展：成本与定价
+    println!("\n\n=====18.2.3.扩展：成本与定价=====");
+    // Cooked 和 FoodV2 都没有价格。这里给每样原材料定一个单价（以分为单位的整数），
+    // 按食谱卡上的用量算出一道菜的成本，再加上可配置的加价率得到售价，最后打印一份带价格的菜单。
+    // 金额全部用 checked_* 运算，溢出不会悄悄回绕，而是作为错误返回。
+    type Cents = u64;
+    #[derive(Debug, Clone, PartialEq)]
+    enum PricingError {
+        NoRecipe(FoodV2),
+        NoUnitCost(Ingredient),
+        Overflow,
+    }
+    impl fmt::Display for PricingError {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            write!(f, "invalid first item to double.")
+            match *self {
+                PricingError::NoRecipe(food) => write!(f, "cannot price {:?} without a recipe", food),
+                PricingError::NoUnitCost(ingredient) => write!(f, "no unit cost for {}", ingredient),
+                PricingError::Overflow => write!(f, "price calculation overflowed"),
+            }
+        }
+    }
+    impl error::Error for PricingError {}
+    fn format_cents(cents: Cents) -> String {
+        format!("${}.{:02}", cents / 100, cents % 100)
+    }
+    struct PriceList {
+        unit_costs: HashMap<Ingredient, Cents>,
+        // 在成本上加价的百分比，比如 150 表示售价是成本的 2.5 倍
+        markup_percent: u64,
+    }
+    impl PriceList {
+        fn dish_cost(&self, food: FoodV2, book: &RecipeBook) -> Result<Cents, PricingError> {
+            let card = book.get(food).ok_or(PricingError::NoRecipe(food))?;
+            card.ingredients().into_iter().try_fold(0, |total: Cents, (ingredient, quantity)| {
+                let unit = *self.unit_costs.get(ingredient).ok_or(PricingError::NoUnitCost(ingredient))?;
+                unit.checked_mul(quantity as Cents)
+                    .and_then(|line| total.checked_add(line))
+                    .ok_or(PricingError::Overflow)
+            })
+        }
+        // 售价 = 成本 × (100 + 加价率) / 100，不足一分的向上取整
+        fn menu_price(&self, food: FoodV2, book: &RecipeBook) -> Result<Cents, PricingError> {
+            let cost = self.dish_cost(food, book)?;
+            100u64
+                .checked_add(self.markup_percent)
+                .and_then(|factor| cost.checked_mul(factor))
+                .and_then(|scaled| scaled.checked_add(99))
+                .map(|scaled| scaled / 100)
+                .ok_or(PricingError::Overflow)
+        }
+        fn print_menu(&self, foods: &[FoodV2], book: &RecipeBook) {
+            for &food in foods {
+                match self.menu_price(food, book) {
+                    Ok(price) => println!("{:<12} {:>8}", format!("{:?}", food), format_cents(price)),
+                    Err(e) => println!("{:<12} {:>8}  ({})", format!("{:?}", food), "-", e),
+                }
+            }
+        }
+    }
+
+    let mut prices = PriceList {
+        unit_costs: HashMap::from([
+            ("beef", 850), ("butter", 40), ("salt", 5),
+            ("rice", 60), ("fish", 700), ("nori", 35),
+        ]),
+        markup_percent: 150,
+    };
+    prices.print_menu(&[FoodV2::Steak, FoodV2::Sushi, FoodV2::CordonBleu], &book);
+    assert_eq!(prices.dish_cost(FoodV2::Steak, &book), Ok(895));
+    assert_eq!(prices.menu_price(FoodV2::Steak, &book), Ok(2238));
+    prices.unit_costs.insert("beef", Cents::MAX / 2);
+    assert_eq!(prices.menu_price(FoodV2::Steak, &book), Err(PricingError::Overflow));
+    // 加价率本身大到离谱也不能 panic
+    prices.unit_costs.insert("beef", 850);
+    prices.markup_percent = u64::MAX;
+    assert_eq!(prices.menu_price(FoodV2::Steak, &book), Err(PricingError::Overflow));
+    //endregion
+
+    //region 18.2.3.扩展：购物清单
+    println!("\n\n=====18.2.3.扩展：购物清单=====");
+    // have_ingredients 拒绝了寿司，却没告诉我们该去买什么。
+    // 给一组想做的菜（比如一周菜单里原本想吃的菜）和当前库存，把所有菜的物料清单加起来，
+    // 减去库存里能用的部分，得到一份合并后的购物清单，按类别分组，可以输出成文本或 CSV。
+    use std::collections::BTreeMap;
+    fn category_of(ingredient: Ingredient) -> &'static str {
+        match ingredient {
+            "beef" | "chicken breast" | "ham" | "fish" => "meat & fish",
+            "butter" | "cheese" => "dairy",
+            "breadcrumbs" | "rice" | "nori" | "salt" => "pantry",
+            _ => "other",
+        }
+    }
+    #[derive(Debug, PartialEq)]
+    struct ShoppingItem {
+        category: &'static str,
+        ingredient: Ingredient,
+        quantity: u32,
+    }
+    #[derive(Debug)]
+    struct ShoppingList {
+        // 按类别、再按原材料名排好序
+        items: Vec<ShoppingItem>,
+    }
+    impl ShoppingList {
+        fn to_text(&self) -> String {
+            let mut text = String::new();
+            let mut current = None;
+            for item in &self.items {
+                if current != Some(item.category) {
+                    text.push_str(&format!("[{}]\n", item.category));
+                    current = Some(item.category);
+                }
+                text.push_str(&format!("  {} x{}\n", item.ingredient, item.quantity));
+            }
+            text
+        }
+        fn to_csv(&self) -> String {
+            let mut csv = String::from("category,ingredient,quantity\n");
+            for item in &self.items {
+                csv.push_str(&format!("{},{},{}\n", item.category, item.ingredient, item.quantity));
+            }
+            csv
+        }
+    }
+    fn shopping_list(dishes: &[FoodV2], inventory: &Inventory) -> ShoppingList {
+        let mut needed: BTreeMap<(&'static str, Ingredient), u32> = BTreeMap::new();
+        for &dish in dishes {
+            for (ingredient, quantity) in bill_of_materials(dish) {
+                *needed.entry((category_of(ingredient), ingredient)).or_insert(0) += quantity;
+            }
+        }
+        let items = needed
+            .into_iter()
+            .filter_map(|((category, ingredient), quantity)| {
+                let missing = quantity.saturating_sub(inventory.available(ingredient));
+                (missing > 0).then_some(ShoppingItem { category, ingredient, quantity: missing })
+            })
+            .collect();
+        ShoppingList { items }
+    }
+
+    let wanted: Vec<FoodV2> = weekly_plan.slots.iter().map(|slot| slot.wanted).collect();
+    let list = shopping_list(&wanted, &inventory);
+    print!("{}", list.to_text());
+    print!("{}", list.to_csv());
+    assert!(list.items.contains(&ShoppingItem { category: "meat & fish", ingredient: "fish", quantity: 5 }));
+    assert!(shopping_list(&[FoodV2::Steak], &inventory).items.is_empty());
+    //endregion
+
+    //region 18.3.结果Result
+    println!("\n\n=====18.3.结果Resul=====");
+    // Result 是 Option 类型的更丰富的版本，描述的是可能的错误而不是可能的不存在。
+    // 也就是说，Result<T, E> 可以有两个结果的其中一个：
+    //      Ok<T>   ：找到 T 元素
+    //      Err<E>  ：找到 E 元素，E 表示错误的类型
+    // 按照约定，预期结果是 "OK"，而意外结果就是 “Err”。
+    // Result 有很多类似于 Option 的方法。例如： unwrap() ，它要么举出元素 T，要么
+    // 就 panic。
+    // 而对于事件的处理，Result 和 Option 有很多相同的组合算子
+    // parse() 方法返回 Result 类型，并不总是能把字符串解析成指定的类型，也有可能失败
+    fn multiply(first_number_str: &str, second_number_str: &str) -> i32 {
+        let first_number = first_number_str.parse::<i32>().unwrap();
+        let second_number = second_number_str.parse::<i32>().unwrap();
+        first_number * second_number
+    }
+    let twenty = multiply("10", "2");
+    println!("double is {}", twenty);
+    // 这里如果第一个参数为字母的话，只能是引发 panic，退出程序。不友好，看下面的例子就好多了。
+    // 2023年2月3日21时19分24秒
+    let tt = multiply("9","2");
+    println!("double is {}", tt);
+    // 失败的情况下，parse()产生一个错误，留给 unwrap() 来解包并产生 panic。
+    // 另外，panic 会退出程序，并提供一个让人很不爽的错误信息
+    //endregion
+
+    //region 18.3.1.Result的map
+    println!("\n\n=====18.3.1.Result的map=====");
+    // 上一节的 multiply 函数的设计不是健壮的 (robust)，一般地，我们希望把错误返回给调用者
+    // 这样它可以决定回应错误的正确方式
+    // 首先，我们需要了解要处理的错误类型是什么。为了确定 Err 的类型，我们可以用 parse() 来
+    // 实验。Rust 已经为 i32 类型使用 FromStr trait 实现了 parse()。结果表明，
+    // 这里的 Err 类型被指定为 ParseIntError。
+    use std::num::ParseIntError;
+    fn multiply_v1(first_number_str: &str, second_number_str: &str) -> Result<i32, ParseIntError> {
+        match first_number_str.parse::<i32>() {
+            Ok(first_number) => {
+                match second_number_str.parse::<i32>() {
+                    Ok(second_number) => {
+                        Ok(first_number * second_number)
+                    },
+                    Err(e) => Err(e),
+                }
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    // 这几个 print_* 原来都是一模一样的 match，现在统一交给最后一节的 report_result 来打印
+    fn print_v1(result: Result<i32, ParseIntError>) {
+        report_result("n is", result.as_ref(), ReportStyle::Plain);
+    }
+
+    let twenty = multiply_v1("10", "2");
+    print_v1(twenty);
+    let tt = multiply_v1("t", "2");
+    print_v1(tt);
+    //endregion
+
+    //region 18.3.2.给Result取别名
+    println!("\n\n=====18.3.2.给Result取别名=====");
+    // 当我们要重用某个 Result 类型时，可以创建别名。
+    // 若某个 Result 有可能被重用，可以方便地给它取一个别名。
+    // 在模块层面上创建别名特别有用。同一模块中的错误常常会有相同的 Err 类型，所以单个别名就能简便定义所有相关
+    // 的 Result 。这太有用了，以至于标准库也提供了一个别名： io::Result
+    // use std::num::ParseIntError;  前面已经定义过了！！！ 2023年2月3日23时57分49秒
+    // 为带有错误类型的 'ParseIntError` 的 'Result` 定义一个泛型别名。
+    type AliasedResult<T> = Result<T, ParseIntError>;
+    // 使用上面定义过的别名来表示上一节中的 `Result<i32, ParseIntError>` 类型
+    fn multiply_v2(first_number_str: &str, second_number_str: &str) -> AliasedResult<i32> {
+        first_number_str.parse::<i32>().and_then(|first_number| {
+            second_number_str.parse::<i32>().map(|second_number| first_number * second_number)
+        })
+    }
+
+    fn print_v2(result: AliasedResult<i32>) {
+        report_result("n is", result.as_ref(), ReportStyle::Plain);
+    }
+
+    print_v2(multiply_v2("10", "2"));
+    print_v2(multiply_v2("r", "2"));
+    //endregion
+
+    //region 18.3.3.提前返回
+    println!("\n\n=====18.3.3.提前返回=====");
+    // 在上一个例子中，显式地使用组合算子处理了错误。
+    // 另一种处理错误的方式是使用 match 语句和提前返回(early return)的结合。
+    // 也就是说，如果发生错误，可以停止函数的执行然后返回错误。
+    #[allow(clippy::question_mark)]
+    fn multiply_v3(first_number_str: &str, second_number_str: &str) -> Result<i32, ParseIntError> {
+        let first_number = match first_number_str.parse::<i32>() {
+            Ok(first_number) => first_number,
+            Err(e) => return Err(e),
+        };
+        let second_number = match second_number_str.parse::<i32>() {
+            Ok(second_number) => second_number,
+            Err(e) => return Err(e),
+        };
+        Ok(first_number * second_number)
+    }
+    fn print_v3(result: Result<i32, ParseIntError>) {
+        report_result("n is", result.as_ref(), ReportStyle::Plain);
+    }
+    print_v3(multiply_v3("10", "2"));
+    print_v3(multiply_v3("z", "2"));
+    // 处理错误的一般原则是，要避免 panic 情况的出现，但显式处理所有错误确实是显得过于繁琐。
+    //endregion
+
+    //region 18.3.4.引入？
+    println!("\n\n=====18.3.4.引入？=====");
+    // 有时我们只是想 unwrap 且产生 panic。到现在为止，对 unwrap 的错误处理都在强迫我们一层层地嵌套，然而我们
+    // 只是想把的变量拿出来，而 ？ 正是为这种情况准备的。
+    // 当找到一个 Err 时，可以采取两种行动：
+    //      1、panic!，不过我们已经决定要尽可能避免 panic
+    //      2、返回它，因为 Err 就意味着它已经不能被处理了
+    // ？ 几乎就等于一个会返回 Err 而不是 panic 的 unwrap！
+    // 数字怎么解析交给 parser：StdParse 就是 str::parse::<i32>，
+    // 换成 NumberFormat 就能接受 " 10 "、"0x1F" 这类写法（见后面的“宽松的数字解析”一节）
+    fn multiply_v4<P: NumberParser>(first_number_str: &str, second_number_str: &str, parser: &P) -> Result<i32, P::Error> {
+        let first_number = parser.parse_i32(first_number_str)?;
+        let second_number = parser.parse_i32(second_number_str)?;
+        Ok(first_number * second_number)
+    }
+    fn print_v4(result: Result<i32, ParseIntError>) {
+        report_result("n is", result.as_ref(), ReportStyle::Plain);
+    }
+    print_v4(multiply_v4("10", "2", &StdParse));
+    print_v4(multiply_v4("z", "2", &StdParse));
+    //endregion
+
+    //region 18.4.处理多种错误类型
+    println!("\n\n=====18.4.处理多种错误类型=====");
+    // 前面出现的例子都是很方便的情况，都是 Result 和其他 Result 交互，还有 Optiont 和其他 Option 交互
+    // 有时 Option 需要和 Result 进行交互，或者 Result<T, Error1> 和 Result<T, Error2> 进行交互。
+    // 在下面的代码中，unwrap 的两个实例生成了不同的错误类型。
+    //      Vec::first 返回一个 Option
+    //      parse::<i32> 返回一个 Result<i32, ParseIntError>
+    fn double_first(vec: Vec<&str>) -> i32 {
+        let first = vec.first().unwrap();
+        2 * first.parse::<i32>().unwrap()
+    }
+    let numbers = vec!["42", "93", "18"];
+    // let empty = vec![];
+    // let strings = vec!["tofu", "93", "18"];
+    println!("The first doubled is {}", double_first(numbers));
+    // println!("The first doubled is {}", double_first(empty));
+    // println!("The first doubled is {}", double_first(strings));
+    //endregion
+
+    //region 18.4.1.从Option中取出Result
+    println!("\n\n=====18.4.1.从Option中取出Result=====");
+    // 处理混合错误类型的最基本的手段就是让它们互相包含。
+    fn double_first_v1(vec: Vec<&str>) -> Option<Result<i32, ParseIntError>> {
+        vec.first().map(|first| {
+            first.parse::<i32>().map(|n| 2 * n)
+        })
+    }
+
+    let numbers = vec!["42", "93", "18"];
+    let empty = vec![];
+    let strings = vec!["tofu", "93", "18"];
+
+    println!("the first doubled is {:?}", double_first_v1(numbers));
+    println!("the first doubled is {:?}", double_first_v1(empty));
+    println!("the first doubled is {:?}", double_first_v1(strings));
+
+    // 有时候我们不想再处理错误（比如使用 ？ 的时候），但如果 Option 是 None 则 继续处理错误
+    // 一些组合算子可以让我们轻松地交换 Result 和 Option。
+    println!("\n使用组合可以让我们轻松地交换 Result 和 Option。");
+    fn double_first_v2<P: NumberParser>(vec: Vec<&str>, parser: &P) -> Result<Option<i32>, P::Error> {
+        let opt = vec.first().map(|first| {
+            parser.parse_i32(first).map(|n| 2 * n)
+        });
+        opt.map_or(Ok(None), |r| r.map(Some))
+    }
+
+    let numbers = vec!["42", "93", "18"];
+    let empty = vec![];
+    let strings = vec!["tofu", "93", "18"];
+
+    println!("the first doubled is {:?}", double_first_v2(numbers, &StdParse));
+    println!("the first doubled is {:?}", double_first_v2(empty, &StdParse));
+    println!("the first doubled is {:?}", double_first_v2(strings, &StdParse));
+    //endregion
+
+    //region 18.4.2.定义一个错误类型
+    println!("\n\n=====18.4.2.定义一个错误类型=====");
+    // 有时候把所有不同的错误都视为一种错误类型会简化代码。
+    // 我们将用一个自定义错误类型来演示这一点。
+    // Rust 允许我们定义自己的错误类型，一般来说，一个“好的”错误类型应当：
+    //      用一个类型代表了多种错误
+    //      向用户提供了清楚的错误信息
+    //      能够容易地与其它类型比较
+    //          好的例子：Err(EmptyVec)
+    //          坏的例子：Err("Please use a vector with at least one element".to_owned())
+    //      能够容纳错误的具体信息
+    //          好的例子：Err(BadChar(c, position))
+    //          坏的例子：Err("+ cannot be used here".to_owned())
+    //      能够与其他错误很好地整合
+    use std::error;
+    use std::fmt;
+    // 定义我们的错误类型，这种类型可以根据错误处理的实际情况定制。
+    // 我们可以完全自定义错误类型，也可以在类型中完全采用底层的错误实现，也可以介于二者之间。
+    #[derive(Debug)]
+    struct DoubleError;
+    type MyResult<T> = std::result::Result<T, DoubleError>;
+
+    // 错误的生成与它如何显示是完全没关系的，没有必要担心的逻辑会导致混乱的显示
+    // 注意：我们没有储存关于错误的任何额外信息，也就是说，如果不修改我们的错误类型定义的话，就
+    // 无法指明是哪个字符串解析失败了。
+    impl fmt::Display for DoubleError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "invalid first item to double.")
+        }
+    }
+     
+    // 为 `DoubleError` 实现 `Error` trait，这样其他错误可以包裹这个错误类型。
+    impl error::Error for DoubleError {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            // 泛型错误，没有记录其内部原因
+            None
+        }
+    }
+
+    fn double_first_v3(vec: Vec<&str>) -> MyResult<i32> {
+        vec.first()
+            .ok_or(DoubleError)
+            .and_then(|s| {
+                s.parse::<i32>()
+                .map_err(|_| DoubleError)
+                .map(|i| 2 * i)
+            })
+    }
+    fn print_double_v3(result: MyResult<i32>) {
+        report_result("The first doubled is", result.as_ref(), ReportStyle::Plain);
+    }
+
+    let numbers = vec!["42", "93", "18"];
+    let empty = vec![];
+    let strings = vec!["tofu", "93", "18"];
+
+    print_double_v3(double_first_v3(numbers));
+    print_double_v3(double_first_v3(empty));
+    print_double_v3(double_first_v3(strings));
+    //endregion
+
+    //region 18.4.3.把错误“装箱”
+    println!("\n\n=====18.4.3.把错误“装箱”=====");
+    // 如果想写简单代码，又想保存原始错误信息，一个方法就是把它们装箱(box)
+    // 唯一的坏处就是，被包装的错误类型只能在运行了解，而不能被 静态地判别。
+    // 对任何实现了 Error trait 的类型，标准库的 Box 通过 From 为它们提供了到 Box<Error> 的转换。
+
+    // 为 `Box<error::Error>` 取别名。
+    type OtherResult<T> = std::result::Result<T, Box<dyn error::Error>>;
+    #[derive(Debug, Clone)]
+    struct EmptyVec;
+    impl fmt::Display for EmptyVec {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "invalid first item to double.")
+        }
+    }
+    impl error::Error for EmptyVec {
+        fn description(&self) -> &str {
+            "invalid first item to double"
+        }
+        fn cause(&self) -> Option<&dyn error::Error> {
+            // 泛型错误。没有记录其内部原因。
+            None
+        }
+    }
+    fn double_first_v4(vec: Vec<&str>) -> OtherResult<i32> {
+        // 我靠，这个太复杂了，顺序怎么办？！2023年2月4日13时17分31秒
+        vec.first()
+            .ok_or_else(|| EmptyVec.into())
+            .and_then(|s| {
+                s.parse::<i32>()
+                    .map_err(|e| e.into())
+                    .map(|i| i * 2)
+            })
+    }
+
+    let numbers = vec!["42", "93", "18"];
+    let empty = vec![];
+    let strings = vec!["tofu", "93", "18"];
+
+    println!("the first doubled is {:?}", double_first_v4(numbers));
+    println!("the first doubled is {:?}", double_first_v4(empty));
+    println!("the first doubled is {:?}", double_first_v4(strings));
+    //endregion
+
+    //region 18.4.4.？的其他用法
+    println!("\n\n=====18.4.4.？的其他用法=====");
+    // 注意在上一个例子中，我们调用 parse 后总是立即将错误从标准库的错误 map（映射）到装箱错误中去：
+    //      .and_then(|s| s.parse::<i32>()
+    //      .map_err(|e| e.into())
+    // 因为这个操作很简单常见，如果有省略写法就好了。
+    // 遗憾的是 and_then 不够灵活，所以实现不了这样的写法。不过，我们可以用 ？ 来代替它。
+    // ？之前被解释为要么 unwrap，要么 return Err(err)，这只是在大多数情况下是正确的。
+    // ？实际上是指 unwrap 或 return Err(From::from(err))。
+    // 由于 From::from 是不同类型之间的转换工具，也就是说，如果在错误可转换成返回类型
+    // 的地方使用 ？，它将自动转换成返回类型。
+    // 我们在这里重写之前的例子，用？，重写后，只要为我们的错误类型实现 From::from ，
+    // 就可以不同去使用 map_err。
+    type AgainResult<T> = std::result::Result<T, Box<dyn error::Error>>;
+    #[derive(Debug)]
+    struct AgainEmptyVec;
+
+    impl fmt::Display for AgainEmptyVec {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "invalid first item to double")
+        }
+    }
+    impl error::Error for AgainEmptyVec {}
+    // 这里的结构和之前的一样，但是这次没有把所有的 `Result` 和 `Option` 串起来，
+    // 而是使用 ？ 立即得到内部值。
+    fn double_first_v5(vec: Vec<&str>) -> AgainResult<i32> {
+        let first = vec.first().ok_or(AgainEmptyVec)?;
+        let parsed = first.parse::<i32>()?;
+        Ok(2 * parsed)
+    }
+    fn print_double_v5(result: AgainResult<i32>) {
+        report_result("The first doubled is", result.as_ref().map_err(|e| e.as_ref()), ReportStyle::Plain);
+    }
+
+    let numbers = vec!["42", "93", "18"];
+    let empty = vec![];
+    let strings = vec!["tofu", "93", "18"];
+
+    print_double_v5(double_first_v5(numbers));
+    print_double_v5(double_first_v5(empty));
+    print_double_v5(double_first_v5(strings));
+    // 此段代码已经相当清楚，与原来的 panic 相比，除了返回类型是 Result 之外，它就像是把所有的
+    // unwrap 调用都换成了 ？ 一样，因此必须在顶层解构它们。
+    //endregion
+
+    //region 18.4.5.包裹错误
+    println!("\n\n=====18.4.5.包裹错误=====");
+    // 把错误装箱这种做法也可以改成把它包裹到你自己的错误类型中去。
+    type ResultV6<T> = std::result::Result<T, DoubleErrorV6>;
+    #[derive(Debug)]
+    enum DoubleErrorV6 {
+        EmptyVec,
+        // 在这个错误类型中，采用 `parse` 的错误类型中 `Err` 部分的实现
+        Parse(ParseIntError),
+    }
+    impl fmt::Display for DoubleErrorV6 {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                DoubleErrorV6::EmptyVec => write!(f, "Please use a vector with at least one element"),
+                DoubleErrorV6::Parse(ref e) => e.fmt(f),
+            }
+        }
+    }
+    impl error::Error for DoubleErrorV6 {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            match *self {
+                DoubleErrorV6::EmptyVec => None,
+                DoubleErrorV6::Parse(ref e) => Some(e),
+            }
+        }
+    }
+    // 实现从 `ParseIntError` 到 `DoubleError` 的转换
+    // 在使用 ？ 时，或者一个 'ParseIntError' 需要转换成 'DoubleErrorV6' 时，它会被自动调用。
+    impl From<ParseIntError> for DoubleErrorV6 {
+        fn from(err: ParseIntError) -> DoubleErrorV6 {
+            DoubleErrorV6::Parse(err)
+        }
+    }
+    fn double_first_v6(vec: Vec<&str>) -> ResultV6<i32> {
+        let first = vec.first().ok_or(DoubleErrorV6::EmptyVec)?;
+        let parsed = first.parse::<i32>()?;
+        Ok(2 * parsed)
+    }
+    fn print_v6(result: ResultV6<i32>) {
+        report_result("The first doubled is", result.as_ref(), ReportStyle::Plain);
+    }
+
+    let numbers = vec!["42", "93", "18"];
+    let empty = vec![];
+    let strings = vec!["tofu", "93", "18"];
+
+    print_v6(double_first_v6(numbers));
+    print_v6(double_first_v6(empty));
+    print_v6(double_first_v6(strings));
+    //endregion
+
+    //region 18.5.遍历Result
+    println!("\n\n=====18.5.遍历Result=====");
+    // 有可能 map 操作失败，可以用 filter_map 过滤掉为 None 的所有结果
+    let strings = vec!["tofu", "93", "18"];
+    let numbers: Vec<_> = strings
+        .into_iter()
+        .filter_map(|s| s.parse::<i32>().ok())
+        .collect();
+    println!("遍历的结果是：{:?}", numbers);
+    //endregion
+
+    //region 18.3.扩展：检查溢出的multiply
+    println!("\n\n=====18.3.扩展：检查溢出的multiply=====");
+    // 18.3 里的 multiply 系列都直接写 first_number * second_number，没有检查溢出：
+    // multiply_v4("100000", "100000") 在 debug 下会 panic，在 release 下会悄悄回绕成一个错误的数。
+    // 这里用 checked_mul，把“解析失败”和“溢出”两种错误包进同一个枚举（做法同 18.4.5），
+    // 再实现 From<ParseIntError>，这样 ? 照样能用。
+    #[derive(Debug, Clone, PartialEq)]
+    enum MultiplyError {
+        Parse(ParseIntError),
+        // 带参数位置的解析错误（见后面的“指出哪个参数出错”一节）
+        Arg(ArgParseError),
+        Overflow,
+    }
+    impl fmt::Display for MultiplyError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                MultiplyError::Parse(ref e) => e.fmt(f),
+                MultiplyError::Arg(ref e) => e.fmt(f),
+                MultiplyError::Overflow => write!(f, "multiplication overflowed"),
+            }
+        }
+    }
+    impl error::Error for MultiplyError {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            match *self {
+                // 这两种的 Display 直接用的是里面那个错误的，错误链从它的 source() 接着往下找，
+                // 否则详细输出会把同一句话打两遍
+                MultiplyError::Parse(ref e) => e.source(),
+                MultiplyError::Arg(ref e) => e.source(),
+                MultiplyError::Overflow => None,
+            }
+        }
+    }
+    impl From<ParseIntError> for MultiplyError {
+        fn from(err: ParseIntError) -> MultiplyError {
+            MultiplyError::Parse(err)
+        }
+    }
+    type CheckedResult<T> = std::result::Result<T, MultiplyError>;
+
+    // 对应 multiply_v1：嵌套 match
+    fn checked_multiply_v1(first_number_str: &str, second_number_str: &str) -> CheckedResult<i32> {
+        match first_number_str.parse::<i32>() {
+            Ok(first_number) => match second_number_str.parse::<i32>() {
+                Ok(second_number) => match first_number.checked_mul(second_number) {
+                    Some(n) => Ok(n),
+                    None => Err(MultiplyError::Overflow),
+                },
+                Err(e) => Err(MultiplyError::Parse(e)),
+            },
+            Err(e) => Err(MultiplyError::Parse(e)),
+        }
+    }
+    // 对应 multiply_v2：组合算子
+    fn checked_multiply_v2(first_number_str: &str, second_number_str: &str) -> CheckedResult<i32> {
+        first_number_str.parse::<i32>().map_err(MultiplyError::from).and_then(|first_number| {
+            second_number_str
+                .parse::<i32>()
+                .map_err(MultiplyError::from)
+                .and_then(|second_number| first_number.checked_mul(second_number).ok_or(MultiplyError::Overflow))
+        })
+    }
+    // 对应 multiply_v3：提前返回
+    #[allow(clippy::question_mark)]
+    fn checked_multiply_v3(first_number_str: &str, second_number_str: &str) -> CheckedResult<i32> {
+        let first_number = match first_number_str.parse::<i32>() {
+            Ok(first_number) => first_number,
+            Err(e) => return Err(e.into()),
+        };
+        let second_number = match second_number_str.parse::<i32>() {
+            Ok(second_number) => second_number,
+            Err(e) => return Err(e.into()),
+        };
+        match first_number.checked_mul(second_number) {
+            Some(n) => Ok(n),
+            None => Err(MultiplyError::Overflow),
+        }
+    }
+    // 对应 multiply_v4：? 会通过 From 把 ParseIntError 转成 MultiplyError
+    fn checked_multiply_v4(first_number_str: &str, second_number_str: &str) -> CheckedResult<i32> {
+        let first_number = first_number_str.parse::<i32>()?;
+        let second_number = second_number_str.parse::<i32>()?;
+        first_number.checked_mul(second_number).ok_or(MultiplyError::Overflow)
+    }
+    fn print_checked(result: CheckedResult<i32>) {
+        report_result("n is", result.as_ref(), ReportStyle::Plain);
+    }
+
+    print_checked(checked_multiply_v4("10", "2"));
+    print_checked(checked_multiply_v4("z", "2"));
+    print_checked(checked_multiply_v4("100000", "100000"));
+    for checked in [checked_multiply_v1, checked_multiply_v2, checked_multiply_v3, checked_multiply_v4] {
+        assert_eq!(checked("10", "2"), Ok(20));
+        assert!(matches!(checked("t", "2"), Err(MultiplyError::Parse(_))));
+        assert_eq!(checked("100000", "100000"), Err(MultiplyError::Overflow));
+    }
+    // 详细输出里解析错误只出现一次
+    assert_eq!(render("n is", checked_multiply_v4("z", "2").as_ref(), ReportStyle::Verbose),
+               "Error: invalid digit found in string");
+    //endregion
+
+    //region 18.3.扩展：泛型的multiply
+    println!("\n\n=====18.3.扩展：泛型的multiply=====");
+    // multiply 系列都写死了 i32。其实 parse 靠的是 FromStr，乘法只要能检查溢出就行，
+    // 所以可以写成泛型：i64、u128、f64，以及自己定义的类型都能用。
+    // 解析错误的类型不再固定是 ParseIntError，而是 <T as FromStr>::Err。
+    // 这一节的类型和函数放在 main 外面（见文件末尾的同名 region），这样 cargo test 的单元测试也能用到
+    println!("{:?}", multiply_generic::<i64>("100000", "100000"));
+    println!("{:?}", multiply_generic::<u128>("18446744073709551616", "4"));
+    println!("{:?}", multiply_generic::<f64>("2.5", "4"));
+    println!("{:?}", multiply_generic::<f64>("2.5x", "4"));
+    println!("{:?}", multiply_generic::<Factor>("3x", "4"));
+    assert_eq!(multiply_generic::<i64>("100000", "100000"), Ok(10_000_000_000));
+    assert_eq!(multiply_generic::<i32>("100000", "100000"), Err(GenericMultiplyError::Overflow));
+    assert_eq!(multiply_generic::<u128>("18446744073709551616", "4"), Ok(73786976294838206464));
+    assert!(matches!(multiply_generic::<u128>("-1", "4"), Err(GenericMultiplyError::Parse(_))));
+    assert_eq!(multiply_generic::<f64>("2.5", "4"), Ok(10.0));
+    assert_eq!(multiply_generic::<f64>("2.5x", "4"), Err(GenericMultiplyError::Parse("2.5x".parse::<f64>().unwrap_err())));
+    assert_eq!(multiply_generic::<f64>("1e308", "10"), Err(GenericMultiplyError::Overflow));
+    assert_eq!(multiply_generic::<i32>("t", "2"), Err(GenericMultiplyError::Parse("t".parse::<i32>().unwrap_err())));
+    assert_eq!(multiply_generic::<Factor>("3x", "4"), Err(GenericMultiplyError::Parse(FactorError::MissingSuffix)));
+    assert_eq!(multiply_generic::<Factor>("3x", "4x"), Ok(Factor(12)));
+    //endregion
+
+    //region 18.3.扩展：表达式求值
+    println!("\n\n=====18.3.扩展：表达式求值=====");
+    // multiply_v4 只能算两个数相乘。这里把它扩展成一个小小的表达式语言：
+    // 支持 + - * / %、括号和负号，所有运算都用 checked_* 做，
+    // 出错时报告出错位置（从 1 开始的列号）：非法字符、多余或缺少的记号、除以零、溢出。
+    #[derive(Debug, Clone, PartialEq)]
+    enum ExprErrorKind {
+        UnexpectedChar(char),
+        UnexpectedToken(String),
+        UnexpectedEnd,
+        Number(ParseIntError),
+        DivideByZero,
+        Overflow,
+    }
+    #[derive(Debug, Clone, PartialEq)]
+    struct ExprError {
+        column: usize,
+        kind: ExprErrorKind,
+    }
+    impl fmt::Display for ExprError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "column {}: ", self.column)?;
+            match self.kind {
+                ExprErrorKind::UnexpectedChar(c) => write!(f, "unexpected character `{}`", c),
+                ExprErrorKind::UnexpectedToken(ref token) => write!(f, "unexpected `{}`", token),
+                ExprErrorKind::UnexpectedEnd => write!(f, "unexpected end of expression"),
+                ExprErrorKind::Number(ref e) => write!(f, "bad number: {}", e),
+                ExprErrorKind::DivideByZero => write!(f, "division by zero"),
+                ExprErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            }
+        }
+    }
+    impl error::Error for ExprError {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            match self.kind {
+                ExprErrorKind::Number(ref e) => Some(e),
+                _ => None,
+            }
+        }
+    }
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Token {
+        // 字面量本身不带符号，用 u32 存，这样 2147483648 也能先读进来，等知道前面有没有负号再检查范围
+        Number(u32),
+        Op(char),
+        Open,
+        Close,
+    }
+    impl fmt::Display for Token {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                Token::Number(n) => write!(f, "{}", n),
+                Token::Op(op) => write!(f, "{}", op),
+                Token::Open => write!(f, "("),
+                Token::Close => write!(f, ")"),
+            }
+        }
+    }
+    // 切成记号，每个记号带上它的列号
+    fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+        let chars: Vec<char> = input.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let column = i + 1;
+            let c = chars[i];
+            match c {
+                ' ' | '\t' => {}
+                '+' | '-' | '*' | '/' | '%' => tokens.push((column, Token::Op(c))),
+                '(' => tokens.push((column, Token::Open)),
+                ')' => tokens.push((column, Token::Close)),
+                '0'..='9' => {
+                    let start = i;
+                    while i + 1 < chars.len() && chars[i + 1].is_ascii_digit() {
+                        i += 1;
+                    }
+                    let digits: String = chars[start..=i].iter().collect();
+                    let n = digits
+                        .parse::<u32>()
+                        .map_err(|e| ExprError { column, kind: ExprErrorKind::Number(e) })?;
+                    tokens.push((column, Token::Number(n)));
+                }
+                _ => return Err(ExprError { column, kind: ExprErrorKind::UnexpectedChar(c) }),
+            }
+            i += 1;
+        }
+        Ok(tokens)
+    }
+    // 递归下降，边解析边求值：
+    //     expr    = term (("+" | "-") term)*
+    //     term    = unary (("*" | "/" | "%") unary)*
+    //     unary   = "-" unary | primary
+    //     primary = number | "(" expr ")"
+    struct ExprParser {
+        tokens: Vec<(usize, Token)>,
+        pos: usize,
+        // 输入末尾之后的列号，用来报告“表达式意外结束”
+        end_column: usize,
+    }
+    impl ExprParser {
+        fn peek(&self) -> Option<(usize, Token)> {
+            self.tokens.get(self.pos).copied()
+        }
+        fn next(&mut self) -> Result<(usize, Token), ExprError> {
+            let token = self.peek().ok_or(ExprError { column: self.end_column, kind: ExprErrorKind::UnexpectedEnd })?;
+            self.pos += 1;
+            Ok(token)
+        }
+        fn apply(column: usize, op: char, lhs: i32, rhs: i32) -> Result<i32, ExprError> {
+            if (op == '/' || op == '%') && rhs == 0 {
+                return Err(ExprError { column, kind: ExprErrorKind::DivideByZero });
+            }
+            let result = match op {
+                '+' => lhs.checked_add(rhs),
+                '-' => lhs.checked_sub(rhs),
+                '*' => lhs.checked_mul(rhs),
+                '/' => lhs.checked_div(rhs),
+                _ => lhs.checked_rem(rhs),
+            };
+            result.ok_or(ExprError { column, kind: ExprErrorKind::Overflow })
+        }
+        fn expr(&mut self) -> Result<i32, ExprError> {
+            let mut value = self.term()?;
+            while let Some((column, Token::Op(op @ ('+' | '-')))) = self.peek() {
+                self.pos += 1;
+                let rhs = self.term()?;
+                value = ExprParser::apply(column, op, value, rhs)?;
+            }
+            Ok(value)
+        }
+        fn term(&mut self) -> Result<i32, ExprError> {
+            let mut value = self.unary()?;
+            while let Some((column, Token::Op(op @ ('*' | '/' | '%')))) = self.peek() {
+                self.pos += 1;
+                let rhs = self.unary()?;
+                value = ExprParser::apply(column, op, value, rhs)?;
+            }
+            Ok(value)
+        }
+        fn unary(&mut self) -> Result<i32, ExprError> {
+            if let Some((column, Token::Op('-'))) = self.peek() {
+                self.pos += 1;
+                // 负号紧跟着字面量时，带上符号再检查范围，这样才写得出 i32::MIN
+                if let Some((literal_column, Token::Number(n))) = self.peek() {
+                    self.pos += 1;
+                    return i32::try_from(-i64::from(n))
+                        .map_err(|_| ExprError { column: literal_column, kind: ExprErrorKind::Overflow });
+                }
+                let value = self.unary()?;
+                return value.checked_neg().ok_or(ExprError { column, kind: ExprErrorKind::Overflow });
+            }
+            self.primary()
+        }
+        fn primary(&mut self) -> Result<i32, ExprError> {
+            match self.next()? {
+                (column, Token::Number(n)) => i32::try_from(n).map_err(|_| ExprError { column, kind: ExprErrorKind::Overflow }),
+                (_, Token::Open) => {
+                    let value = self.expr()?;
+                    match self.next()? {
+                        (_, Token::Close) => Ok(value),
+                        (column, token) => Err(ExprError { column, kind: ExprErrorKind::UnexpectedToken(token.to_string()) }),
+                    }
+                }
+                (column, token) => Err(ExprError { column, kind: ExprErrorKind::UnexpectedToken(token.to_string()) }),
+            }
+        }
+    }
+    fn evaluate(input: &str) -> Result<i32, ExprError> {
+        let mut parser = ExprParser { tokens: tokenize(input)?, pos: 0, end_column: input.chars().count() + 1 };
+        let value = parser.expr()?;
+        // 整个表达式读完之后不应该还有剩下的记号
+        match parser.peek() {
+            None => Ok(value),
+            Some((column, token)) => Err(ExprError { column, kind: ExprErrorKind::UnexpectedToken(token.to_string()) }),
+        }
+    }
+
+    for input in ["10 * 2", "-(3 + 4) * 2 % 5", "7 / (2 - 2)", "100000 * 100000", "2 * (3 + x)", "(1 + 2", "1 2"] {
+        match evaluate(input) {
+            Ok(n) => println!("{} = {}", input, n),
+            Err(e) => println!("{} => Error: {}", input, e),
+        }
+    }
+    assert_eq!(evaluate("10 * 2"), Ok(20));
+    assert_eq!(evaluate("-(3 + 4) * 2 % 5"), Ok(-4));
+    assert_eq!(evaluate("1 - 2 - 3"), Ok(-4));
+    assert_eq!(evaluate("7 / (2 - 2)"), Err(ExprError { column: 3, kind: ExprErrorKind::DivideByZero }));
+    assert_eq!(evaluate("100000 * 100000"), Err(ExprError { column: 8, kind: ExprErrorKind::Overflow }));
+    assert_eq!(evaluate("2 * (3 + x)"), Err(ExprError { column: 10, kind: ExprErrorKind::UnexpectedChar('x') }));
+    assert_eq!(evaluate("(1 + 2"), Err(ExprError { column: 7, kind: ExprErrorKind::UnexpectedEnd }));
+    assert_eq!(evaluate("-2147483648"), Ok(i32::MIN));
+    assert_eq!(evaluate("2147483648"), Err(ExprError { column: 1, kind: ExprErrorKind::Overflow }));
+    assert_eq!(evaluate("-2147483649"), Err(ExprError { column: 2, kind: ExprErrorKind::Overflow }));
+    assert_eq!(evaluate("-(-2147483648)"), Err(ExprError { column: 1, kind: ExprErrorKind::Overflow }));
+    assert_eq!(evaluate("99999999999"), Err(ExprError { column: 1, kind: ExprErrorKind::Number("99999999999".parse::<u32>().unwrap_err()) }));
+    //endregion
+
+    //region 18.3.扩展：交互式计算器
+    println!("\n\n=====18.3.扩展：交互式计算器=====");
+    // 想换个输入试试，就得改掉写死的 "10", "2"、"t", "2" 再重新编译。
+    // `cargo run -- repl` 会进入一个按行读取标准输入的计算器：
+    //      multiply 10 2       用 multiply_v5 相乘（检查溢出，出错时指出是第几个参数）
+    //      double 42 93 18     用 double_first_v6 把第一个数翻倍
+    //      eval -(3 + 4) * 2   或者直接输入表达式，用 evaluate 求值
+    //      quit                退出（读到输入结束也会退出）
+    // 出错时打印整条错误链（一路沿着 source() 往下找），然后继续等下一行，输错了也不会退出。
+    use std::io::{self, BufRead, Write};
+    fn error_chain(e: &dyn error::Error) -> String {
+        let mut chain = e.to_string();
+        for cause in causes(e) {
+            chain.push_str(&format!("\n    caused by: {}", cause));
+        }
+        chain
+    }
+    fn repl_line(line: &str) -> Result<String, Box<dyn error::Error>> {
+        let command_line = line.trim_start();
+        let (command, rest) = command_line.split_once(' ').unwrap_or((command_line, ""));
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        match command {
+            "multiply" => match args[..] {
+                [first, second] => Ok(multiply_v5(first, second)?.to_string()),
+                _ => Err("usage: multiply <a> <b>".into()),
+            },
+            "double" => Ok(double_first_v6(args)?.to_string()),
+            // 报错的列号要算上前面的缩进和 "eval "，对应用户敲的整行
+            "eval" => {
+                let prefix = line.chars().count() - rest.chars().count();
+                let value = evaluate(rest).map_err(|e| ExprError { column: e.column + prefix, ..e })?;
+                Ok(value.to_string())
+            }
+            _ => Ok(evaluate(line)?.to_string()),
+        }
+    }
+    fn run_repl<R: BufRead, W: Write>(input: R, output: &mut W) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+        // 按字节读一行再自己解码：lines() 碰到不是 UTF-8 的输入会直接返回 Err，整个 REPL 就退出了
+        for line in input.split(b'\n') {
+            let mut line = line?;
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            let reply = match String::from_utf8(line) {
+                Err(e) => Some(Err(e.into())),
+                Ok(line) => match line.trim() {
+                    "" => None,
+                    "quit" | "exit" => break,
+                    // 只去掉行尾空白，行首的缩进要留给 repl_line 算列号
+                    _ => Some(repl_line(line.trim_end())),
+                },
+            };
+            match reply {
+                Some(Ok(value)) => writeln!(output, "{}", value)?,
+                Some(Err(e)) => writeln!(output, "Error: {}", error_chain(e.as_ref()))?,
+                None => {}
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+
+    // 用一段写好的输入演示一遍
+    let script = "multiply 10 2\nmultiply t 2\ndouble\ndouble 21 5\neval 7 / 0\n(1 + 2) * 3\nquit\nmultiply 1 1\n";
+    let mut transcript = Vec::new();
+    run_repl(script.as_bytes(), &mut transcript).expect("writing to a Vec cannot fail");
+    let transcript = String::from_utf8(transcript).expect("the repl only writes UTF-8");
+    print!("{}", transcript);
+    assert!(transcript.contains("> 20\n"));
+    assert!(transcript.contains("Error: argument 1 (\"t\") is not a valid number\n    caused by: invalid digit found in string"));
+    assert!(transcript.contains("> 9\n"));
+    assert!(!transcript.contains("> 1\n"));
+    // "eval 7 / 0" 里的 `/` 在整行的第 8 列
+    assert!(transcript.contains("column 8: division by zero"));
+    // 带缩进的输入，列号也按整行算
+    assert_eq!(repl_line("   eval 7 / 0").unwrap_err().to_string(), "column 11: division by zero");
+    assert_eq!(repl_line("  7 / 0").unwrap_err().to_string(), "column 5: division by zero");
+    // 中间夹一行不是 UTF-8 的输入，报个错接着读下一行
+    let mut transcript = Vec::new();
+    run_repl(&b"multiply 1 2\n\xff\xfe\nmultiply 3 4\n"[..], &mut transcript).expect("writing to a Vec cannot fail");
+    let transcript = String::from_utf8(transcript).expect("the repl only writes UTF-8");
+    print!("{}", transcript);
+    assert!(transcript.contains("> Error: invalid utf-8"));
+    assert!(transcript.contains("> 12\n"));
+    //endregion
+
+    //region 18.3.扩展：指出哪个参数出错
+    println!("\n\n=====18.3.扩展：指出哪个参数出错=====");
+    // multiply_v4("z", "2") 失败时只打印 "invalid digit found in string"，
+    // 看不出是第几个参数、原文是什么。这里把参数下标、原始输入和底层的 ParseIntError 一起包起来，
+    // source() 返回原来的 ParseIntError，错误链不会断。
+    // 乘法本身用 checked_mul，错误统一成 MultiplyError，解析错误放在 MultiplyError::Arg 里。
+    // 18.3 正文里的 multiply_v4/print_v4 保持原样：那是在演示 ? 怎么替换 match，
+    // 溢出检查的版本是前面的 checked_multiply_v4，这里是在它的基础上再补上参数位置。
+    #[derive(Debug, Clone, PartialEq)]
+    struct ArgParseError {
+        // 从 0 开始的参数下标
+        index: usize,
+        input: String,
+        source: ParseIntError,
+    }
+    impl fmt::Display for ArgParseError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "argument {} ({:?}) is not a valid number", self.index + 1, self.input)
+        }
+    }
+    impl error::Error for ArgParseError {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            Some(&self.source)
+        }
+    }
+    fn parse_arg(index: usize, input: &str) -> Result<i32, ArgParseError> {
+        input.parse::<i32>().map_err(|source| ArgParseError { index, input: input.to_string(), source })
+    }
+    impl From<ArgParseError> for MultiplyError {
+        fn from(err: ArgParseError) -> MultiplyError {
+            MultiplyError::Arg(err)
+        }
+    }
+    // 和 checked_multiply_v4 一样用 ?，只是错误里多了位置和原文
+    fn multiply_v5(first_number_str: &str, second_number_str: &str) -> CheckedResult<i32> {
+        let first_number = parse_arg(0, first_number_str)?;
+        let second_number = parse_arg(1, second_number_str)?;
+        first_number.checked_mul(second_number).ok_or(MultiplyError::Overflow)
+    }
+    fn print_v5(result: CheckedResult<i32>) {
+        report_result("n is", result.as_ref(), ReportStyle::Verbose);
+    }
+
+    print_v5(multiply_v5("10", "2"));
+    print_v5(multiply_v5("z", "2"));
+    print_v5(multiply_v5("10", "two"));
+    print_v5(multiply_v5("100000", "100000"));
+    let err = multiply_v5("10", "two").unwrap_err();
+    match err {
+        MultiplyError::Arg(ref arg) => assert_eq!((arg.index, arg.input.as_str()), (1, "two")),
+        ref other => panic!("expected an argument error, got {:?}", other),
+    }
+    assert_eq!(error::Error::source(&err).map(|e| e.to_string()), Some("invalid digit found in string".to_string()));
+    assert_eq!(multiply_v5("100000", "100000"), Err(MultiplyError::Overflow));
+    //endregion
+
+    //region 18.3.扩展：宽松的数字解析
+    println!("\n\n=====18.3.扩展：宽松的数字解析=====");
+    // str::parse::<i32> 很严格：" 10 "、"1_000"、"0x1F"、"1,000" 统统不认。
+    // NumberFormat 把这些写法做成可以逐项打开的选项：去掉首尾空白、数字分隔符、0x/0o/0b 前缀、千位分隔符。
+    // 被拒绝时返回具体的错误种类，而不是笼统的 “invalid digit”。
+    // multiply_v4 和 double_first_v2 通过 NumberParser 选用解析规则，传 StdParse 就是原来的严格解析。
+    trait NumberParser {
+        type Error;
+        fn parse_i32(&self, s: &str) -> Result<i32, Self::Error>;
+    }
+    struct StdParse;
+    impl NumberParser for StdParse {
+        type Error = ParseIntError;
+        fn parse_i32(&self, s: &str) -> Result<i32, ParseIntError> {
+            s.parse::<i32>()
+        }
+    }
+    #[derive(Debug, Clone, Copy, Default)]
+    struct NumberFormat {
+        trim: bool,
+        // 比如 '_'：1_000
+        digit_separator: Option<char>,
+        // 是否接受 0x1F、0o17、0b101
+        radix_prefixes: bool,
+        // 比如 ','：1,000,000，只用于十进制，且必须三位一组
+        thousands_separator: Option<char>,
+    }
+    #[derive(Debug, Clone, PartialEq)]
+    enum LenientErrorKind {
+        Empty,
+        SurroundingWhitespace,
+        PrefixNotAllowed(String),
+        MisplacedSeparator(char),
+        BadGrouping,
+        InvalidDigit { digit: char, radix: u32 },
+        Overflow,
+    }
+    #[derive(Debug, Clone, PartialEq)]
+    struct LenientParseError {
+        input: String,
+        kind: LenientErrorKind,
+    }
+    impl fmt::Display for LenientParseError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "cannot parse {:?}: ", self.input)?;
+            match self.kind {
+                LenientErrorKind::Empty => write!(f, "no digits"),
+                LenientErrorKind::SurroundingWhitespace => write!(f, "leading or trailing whitespace"),
+                LenientErrorKind::PrefixNotAllowed(ref prefix) => write!(f, "radix prefix `{}` is not enabled", prefix),
+                LenientErrorKind::MisplacedSeparator(c) => write!(f, "separator `{}` must sit between digits", c),
+                LenientErrorKind::BadGrouping => write!(f, "thousands separators must group exactly three digits"),
+                LenientErrorKind::InvalidDigit { digit, radix } => write!(f, "`{}` is not a base-{} digit", digit, radix),
+                LenientErrorKind::Overflow => write!(f, "number does not fit in i32"),
+            }
+        }
+    }
+    impl error::Error for LenientParseError {}
+    fn parse_lenient(input: &str, format: &NumberFormat) -> Result<i32, LenientParseError> {
+        let fail = |kind| LenientParseError { input: input.to_string(), kind };
+        let text = if format.trim {
+            input.trim()
+        } else if input.trim() != input {
+            return Err(fail(LenientErrorKind::SurroundingWhitespace));
+        } else {
+            input
+        };
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let lower = text.to_ascii_lowercase();
+        let (radix, mut body) = match lower.get(..2) {
+            Some(prefix @ ("0x" | "0o" | "0b")) => {
+                if !format.radix_prefixes {
+                    return Err(fail(LenientErrorKind::PrefixNotAllowed(text[..2].to_string())));
+                }
+                let radix = match prefix { "0x" => 16, "0o" => 8, _ => 2 };
+                (radix, text[2..].to_string())
+            }
+            _ => (10, text.to_string()),
+        };
+        if let Some(sep) = format.digit_separator {
+            if body.starts_with(sep) || body.ends_with(sep) || body.contains(&format!("{}{}", sep, sep)) {
+                return Err(fail(LenientErrorKind::MisplacedSeparator(sep)));
+            }
+            body = body.replace(sep, "");
+        }
+        if let (Some(sep), 10) = (format.thousands_separator, radix) {
+            if body.contains(sep) {
+                let groups: Vec<&str> = body.split(sep).collect();
+                let well_formed = (1..=3).contains(&groups[0].len()) && groups[1..].iter().all(|group| group.len() == 3);
+                if !well_formed {
+                    return Err(fail(LenientErrorKind::BadGrouping));
+                }
+                body = groups.concat();
+            }
+        }
+        if body.is_empty() {
+            return Err(fail(LenientErrorKind::Empty));
+        }
+        // 按负数累加，这样 i32::MIN 也能表示
+        let mut value: i32 = 0;
+        for c in body.chars() {
+            let digit = c.to_digit(radix).ok_or_else(|| fail(LenientErrorKind::InvalidDigit { digit: c, radix }))? as i32;
+            value = value
+                .checked_mul(radix as i32)
+                .and_then(|v| v.checked_sub(digit))
+                .ok_or_else(|| fail(LenientErrorKind::Overflow))?;
+        }
+        if negative {
+            Ok(value)
+        } else {
+            value.checked_neg().ok_or_else(|| fail(LenientErrorKind::Overflow))
         }
     }
-     
-    // 为 `DoubleError` 实现 `Error` trait，这样其他错误可以包裹这个错误类型。
-    impl error::Error for DoubleError {
-        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-            // 泛型错误，没有记录其内部原因
-            None
+    impl NumberParser for NumberFormat {
+        type Error = LenientParseError;
+        fn parse_i32(&self, s: &str) -> Result<i32, LenientParseError> {
+            parse_lenient(s, self)
         }
     }
-
-    fn double_first_v3(vec: Vec<&str>) -> MyResult<i32> {
-        vec.first()
-            .ok_or(DoubleError)
-            .and_then(|s| {
-                s.parse::<i32>()
-                .map_err(|_| DoubleError)
-                .map(|i| 2 * i)
-            })
+    // 乘出来溢出不是解析错误，和 MultiplyError 一样单独分一类
+    #[derive(Debug, Clone, PartialEq)]
+    enum LenientMultiplyError {
+        Parse(LenientParseError),
+        Overflow,
     }
-    fn print_double_v3(result: MyResult<i32>) {
-        match result {
-            Ok(n) => println!("The first doubled is {}", n),
-            Err(e) => println!("Error: {}", e),
+    impl fmt::Display for LenientMultiplyError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                LenientMultiplyError::Parse(ref e) => e.fmt(f),
+                LenientMultiplyError::Overflow => write!(f, "multiplication overflowed"),
+            }
         }
     }
+    impl error::Error for LenientMultiplyError {}
+    impl From<LenientParseError> for LenientMultiplyError {
+        fn from(err: LenientParseError) -> LenientMultiplyError {
+            LenientMultiplyError::Parse(err)
+        }
+    }
+    // checked_multiply_v4 的宽松版本
+    fn multiply_lenient(first_number_str: &str, second_number_str: &str, format: &NumberFormat)
+                        -> Result<i32, LenientMultiplyError> {
+        let first_number = parse_lenient(first_number_str, format)?;
+        let second_number = parse_lenient(second_number_str, format)?;
+        first_number.checked_mul(second_number).ok_or(LenientMultiplyError::Overflow)
+    }
+    // double_first_v2 的宽松且检查溢出的版本：空的 vec 得到 Ok(None)
+    fn double_first_lenient(vec: Vec<&str>, format: &NumberFormat) -> Result<Option<i32>, LenientMultiplyError> {
+        vec.first()
+            .map(|first| parse_lenient(first, format)?.checked_mul(2).ok_or(LenientMultiplyError::Overflow))
+            .transpose()
+    }
 
-    let numbers = vec!["42", "93", "18"];
-    let empty = vec![];
-    let strings = vec!["tofu", "93", "18"];
-
-    print_double_v3(double_first_v3(numbers));
-    print_double_v3(double_first_v3(empty));
-    print_double_v3(double_first_v3(strings));
+    let relaxed = NumberFormat {
+        trim: true,
+        digit_separator: Some('_'),
+        radix_prefixes: true,
+        thousands_separator: Some(','),
+    };
+    let strict = NumberFormat::default();
+    for input in [" 10 ", "1_000", "0x1F", "-0b101", "1,000", "1,00", "1__0", "0x1G", "3000000000"] {
+        report_result(&format!("{:?} ->", input), parse_lenient(input, &relaxed).as_ref(), ReportStyle::Plain);
+    }
+    println!("{:?}", multiply_v4(" 10 ", "0x1F", &relaxed));
+    println!("{:?}", double_first_v2(vec!["1_000", "93"], &relaxed));
+    println!("{:?}", multiply_lenient("1,000", "0x10", &relaxed));
+    println!("{:?}", double_first_lenient(vec![" 21 ", "93"], &relaxed));
+    assert_eq!(parse_lenient("0x1F", &relaxed), Ok(31));
+    assert_eq!(parse_lenient("-2147483648", &strict), Ok(i32::MIN));
+    assert_eq!(parse_lenient(" 10 ", &strict).unwrap_err().kind, LenientErrorKind::SurroundingWhitespace);
+    assert_eq!(parse_lenient("0x1F", &strict).unwrap_err().kind, LenientErrorKind::PrefixNotAllowed("0x".to_string()));
+    assert_eq!(parse_lenient("1_000", &strict).unwrap_err().kind, LenientErrorKind::InvalidDigit { digit: '_', radix: 10 });
+    assert_eq!(parse_lenient("1,00", &relaxed).unwrap_err().kind, LenientErrorKind::BadGrouping);
+    assert_eq!(parse_lenient("1__0", &relaxed).unwrap_err().kind, LenientErrorKind::MisplacedSeparator('_'));
+    assert_eq!(multiply_lenient("1,000", "0x10", &relaxed), Ok(16000));
+    assert_eq!(double_first_lenient(vec![], &relaxed), Ok(None));
+    assert_eq!(multiply_v4(" 10 ", "0x1F", &relaxed), Ok(310));
+    assert_eq!(multiply_v4(" 10 ", "2", &strict).unwrap_err().kind, LenientErrorKind::SurroundingWhitespace);
+    assert_eq!(double_first_v2(vec!["1_000"], &relaxed), Ok(Some(2000)));
+    assert_eq!(multiply_lenient("65536", "65536", &relaxed), Err(LenientMultiplyError::Overflow));
+    assert_eq!(double_first_lenient(vec!["0x7FFFFFFF"], &relaxed), Err(LenientMultiplyError::Overflow));
     //endregion
 
-    //region 18.4.3.把错误“装箱”
-    println!("\n\n=====18.4.3.把错误“装箱”=====");
-    // 如果想写简单代码，又想保存原始错误信息，一个方法就是把它们装箱(box)
-    // 唯一的坏处就是，被包装的错误类型只能在运行了解，而不能被 静态地判别。
-    // 对任何实现了 Error trait 的类型，标准库的 Box 通过 From 为它们提供了到 Box<Error> 的转换。
-
-    // 为 `Box<error::Error>` 取别名。
-    type OtherResult<T> = std::result::Result<T, Box<dyn error::Error>>;
-    #[derive(Debug, Clone)]
-    struct EmptyVec;
-    impl fmt::Display for EmptyVec {
+    //region 18.3.扩展：大整数
+    println!("\n\n=====18.3.扩展：大整数=====");
+    // i32 装不下大数的乘积。这里不引入依赖，自己写一个最简单的大整数：
+    // 符号 + 按 10^9 分段的绝对值（低位在前），支持从十进制字符串解析、加法、乘法和 Display。
+    use std::cmp::Ordering;
+    use std::ops::{Add, Mul};
+    const BIG_BASE: u64 = 1_000_000_000;
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct BigInt {
+        negative: bool,
+        // 每个元素是 0..10^9 之间的一段，低位在前；零用空的 Vec 表示
+        limbs: Vec<u32>,
+    }
+    #[derive(Debug, Clone, PartialEq)]
+    enum BigIntParseError {
+        Empty,
+        InvalidDigit { digit: char, position: usize },
+    }
+    impl fmt::Display for BigIntParseError {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            write!(f, "invalid first item to double.")
+            match *self {
+                BigIntParseError::Empty => write!(f, "cannot parse an integer from an empty string"),
+                BigIntParseError::InvalidDigit { digit, position } =>
+                    write!(f, "invalid digit `{}` at position {}", digit, position),
+            }
         }
     }
-    impl error::Error for EmptyVec {
-        fn description(&self) -> &str {
-            "invalid first item to double"
+    impl error::Error for BigIntParseError {}
+    impl BigInt {
+        fn normalized(mut self) -> BigInt {
+            while self.limbs.last() == Some(&0) {
+                self.limbs.pop();
+            }
+            if self.limbs.is_empty() {
+                self.negative = false;
+            }
+            self
         }
-        fn cause(&self) -> Option<&dyn error::Error> {
-            // 泛型错误。没有记录其内部原因。
-            None
+        fn compare_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+            a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+        }
+        fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+            let mut limbs = Vec::with_capacity(a.len().max(b.len()) + 1);
+            let mut carry = 0;
+            for i in 0..a.len().max(b.len()) {
+                let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+                limbs.push((sum % BIG_BASE) as u32);
+                carry = sum / BIG_BASE;
+            }
+            if carry > 0 {
+                limbs.push(carry as u32);
+            }
+            limbs
+        }
+        // 要求 |a| >= |b|
+        fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+            let mut limbs = Vec::with_capacity(a.len());
+            let mut borrow = 0;
+            for (i, &limb) in a.iter().enumerate() {
+                let mut diff = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+                borrow = 0;
+                if diff < 0 {
+                    diff += BIG_BASE as i64;
+                    borrow = 1;
+                }
+                limbs.push(diff as u32);
+            }
+            limbs
         }
     }
-    fn double_first_v4(vec: Vec<&str>) -> OtherResult<i32> {
-        // 我靠，这个太复杂了，顺序怎么办？！2023年2月4日13时17分31秒
-        vec.first()
-            .ok_or_else(|| EmptyVec.into())
-            .and_then(|s| {
-                s.parse::<i32>()
-                    .map_err(|e| e.into())
-                    .map(|i| i * 2)
-            })
+    impl FromStr for BigInt {
+        type Err = BigIntParseError;
+        fn from_str(s: &str) -> Result<BigInt, BigIntParseError> {
+            let negative = s.starts_with('-');
+            let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+            let offset = s.len() - digits.len();
+            if digits.is_empty() {
+                return Err(BigIntParseError::Empty);
+            }
+            if let Some((i, digit)) = digits.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+                return Err(BigIntParseError::InvalidDigit { digit, position: offset + i });
+            }
+            // 从低位往高位每 9 位切一段
+            let bytes = digits.as_bytes();
+            let limbs = bytes
+                .rchunks(9)
+                .map(|chunk| chunk.iter().fold(0u32, |n, b| n * 10 + (b - b'0') as u32))
+                .collect();
+            Ok(BigInt { negative, limbs }.normalized())
+        }
     }
-
-    let numbers = vec!["42", "93", "18"];
-    let empty = vec![];
-    let strings = vec!["tofu", "93", "18"];
-
-    println!("the first doubled is {:?}", double_first_v4(numbers));
-    println!("the first doubled is {:?}", double_first_v4(empty));
-    println!("the first doubled is {:?}", double_first_v4(strings));
-    //endregion
-
-    //region 18.4.4.？的其他用法
-    println!("\n\n=====18.4.4.？的其他用法=====");
-    // 注意在上一个例子中，我们调用 parse 后总是立即将错误从标准库的错误 map（映射）到装箱错误中去：
-    //      .and_then(|s| s.parse::<i32>()
-    //      .map_err(|e| e.into())
-    // 因为这个操作很简单常见，如果有省略写法就好了。
-    // 遗憾的是 and_then 不够灵活，所以实现不了这样的写法。不过，我们可以用 ？ 来代替它。
-    // ？之前被解释为要么 unwrap，要么 return Err(err)，这只是在大多数情况下是正确的。
-    // ？实际上是指 unwrap 或 return Err(From::from(err))。
-    // 由于 From::from 是不同类型之间的转换工具，也就是说，如果在错误可转换成返回类型
-    // 的地方使用 ？，它将自动转换成返回类型。
-    // 我们在这里重写之前的例子，用？，重写后，只要为我们的错误类型实现 From::from ，
-    // 就可以不同去使用 map_err。
-    type AgainResult<T> = std::result::Result<T, Box<dyn error::Error>>;
-    #[derive(Debug)]
-    struct AgainEmptyVec;
-
-    impl fmt::Display for AgainEmptyVec {
-        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-            write!(f, "invalid first item to double")
+    impl fmt::Display for BigInt {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let mut limbs = self.limbs.iter().rev();
+            match limbs.next() {
+                None => return write!(f, "0"),
+                Some(top) => write!(f, "{}{}", if self.negative { "-" } else { "" }, top)?,
+            }
+            for limb in limbs {
+                write!(f, "{:09}", limb)?;
+            }
+            Ok(())
         }
     }
-    impl error::Error for AgainEmptyVec {}
-    // 这里的结构和之前的一样，但是这次没有把所有的 `Result` 和 `Option` 串起来，
-    // 而是使用 ？ 立即得到内部值。
-    fn double_first_v5(vec: Vec<&str>) -> AgainResult<i32> {
-        let first = vec.first().ok_or(AgainEmptyVec)?;
-        let parsed = first.parse::<i32>()?;
-        Ok(2 * parsed)
+    impl Add for &BigInt {
+        type Output = BigInt;
+        fn add(self, rhs: &BigInt) -> BigInt {
+            if self.negative == rhs.negative {
+                return BigInt { negative: self.negative, limbs: BigInt::add_magnitude(&self.limbs, &rhs.limbs) };
+            }
+            // 符号相反：大的绝对值减小的，符号跟大的走
+            match BigInt::compare_magnitude(&self.limbs, &rhs.limbs) {
+                Ordering::Less => BigInt { negative: rhs.negative, limbs: BigInt::sub_magnitude(&rhs.limbs, &self.limbs) },
+                _ => BigInt { negative: self.negative, limbs: BigInt::sub_magnitude(&self.limbs, &rhs.limbs) },
+            }
+            .normalized()
+        }
     }
-    fn print_double_v5(result: AgainResult<i32>) {
-       match result {
-           Ok(n) => println!("The first doubled is {}", n),
-           Err(e) => println!("Error: {}", e),
-       }
+    impl Mul for &BigInt {
+        type Output = BigInt;
+        // 竖式乘法
+        fn mul(self, rhs: &BigInt) -> BigInt {
+            let mut limbs = vec![0u64; self.limbs.len() + rhs.limbs.len()];
+            for (i, &a) in self.limbs.iter().enumerate() {
+                let mut carry = 0;
+                for (j, &b) in rhs.limbs.iter().enumerate() {
+                    let current = limbs[i + j] + a as u64 * b as u64 + carry;
+                    limbs[i + j] = current % BIG_BASE;
+                    carry = current / BIG_BASE;
+                }
+                limbs[i + rhs.limbs.len()] += carry;
+            }
+            BigInt { negative: self.negative != rhs.negative, limbs: limbs.into_iter().map(|limb| limb as u32).collect() }
+                .normalized()
+        }
+    }
+    fn multiply_big(first_number_str: &str, second_number_str: &str) -> Result<BigInt, BigIntParseError> {
+        let first_number = first_number_str.parse::<BigInt>()?;
+        let second_number = second_number_str.parse::<BigInt>()?;
+        Ok(&first_number * &second_number)
     }
 
-    let numbers = vec!["42", "93", "18"];
-    let empty = vec![];
-    let strings = vec!["tofu", "93", "18"];
+    report_result("n is", multiply_big("123456789012345678901234567890", "98765").as_ref(), ReportStyle::Plain);
+    report_result("n is", multiply_big("12x", "2").as_ref(), ReportStyle::Plain);
+    assert_eq!(multiply_big("123456789012345678901234567890", "98765").map(|n| n.to_string()),
+               Ok("12193209766804320976680432097655850".to_string()));
+    let product = multiply_big("-999999999999", "1000000001").expect("both are valid numbers");
+    let five: BigInt = "5".parse().expect("5 is a valid number");
+    assert_eq!((&product + &five).to_string(), "-1000000000998999999994");
+    assert_eq!((&"-7".parse::<BigInt>().unwrap() + &"7".parse::<BigInt>().unwrap()).to_string(), "0");
+    assert_eq!(multiply_big("-0", "17").map(|n| n.to_string()), Ok("0".to_string()));
+    assert_eq!(multiply_big("12x", "2"), Err(BigIntParseError::InvalidDigit { digit: 'x', position: 2 }));
+    //endregion
 
-    print_double_v5(double_first_v5(numbers));
-    print_double_v5(double_first_v5(empty));
-    print_double_v5(double_first_v5(strings));
-    // 此段代码已经相当清楚，与原来的 panic 相比，除了返回类型是 Result 之外，它就像是把所有的
-    // unwrap 调用都换成了 ？ 一样，因此必须在顶层解构它们。
+    //region 18.3.扩展：整组相乘
+    println!("\n\n=====18.3.扩展：整组相乘=====");
+    // multiply 只能接收两个 &str。product_all 把一整组数字字符串乘起来：
+    // 要么得到乘积，要么得到全部出错的位置和原因（不只是第一个）；product_all_short 则遇到第一个错误就停。
+    // 和 18.3.2 一样先给 Result 取别名。错误用 18.3 检查溢出那一节的 MultiplyError，溢出记在让它溢出的那一项上。
+    type IndexedResult<T> = std::result::Result<T, (usize, MultiplyError)>;
+    type CollectedResult<T> = std::result::Result<T, Vec<(usize, MultiplyError)>>;
+    fn product_all_short(numbers: &[&str]) -> IndexedResult<i32> {
+        numbers.iter().enumerate().try_fold(1i32, |product, (i, s)| {
+            let n = s.parse::<i32>().map_err(|e| (i, MultiplyError::from(e)))?;
+            product.checked_mul(n).ok_or((i, MultiplyError::Overflow))
+        })
+    }
+    fn product_all(numbers: &[&str]) -> CollectedResult<i32> {
+        let (parsed, errors): (Vec<_>, Vec<_>) = numbers
+            .iter()
+            .enumerate()
+            .map(|(i, s)| s.parse::<i32>().map(|n| (i, n)).map_err(|e| (i, MultiplyError::from(e))))
+            .partition(|result| result.is_ok());
+        if !errors.is_empty() {
+            return Err(errors.into_iter().filter_map(Result::err).collect());
+        }
+        parsed
+            .into_iter()
+            .filter_map(Result::ok)
+            .try_fold(1i32, |product, (i, n)| product.checked_mul(n).ok_or((i, MultiplyError::Overflow)))
+            .map_err(|e| vec![e])
+    }
+
+    let numbers = ["10", "2", "3"];
+    let messy = ["10", "x", "3", "", "4"];
+    println!("{:?}", product_all(&numbers));
+    match product_all(&messy) {
+        Ok(n) => println!("n is {}", n),
+        Err(errors) => {
+            for (i, e) in errors {
+                println!("Error at {}: {}", i, e);
+            }
+        }
+    }
+    println!("{:?}", product_all_short(&messy));
+    assert_eq!(product_all(&numbers), Ok(60));
+    assert_eq!(product_all(&[]), Ok(1));
+    assert_eq!(product_all(&messy).unwrap_err().iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(product_all_short(&messy).unwrap_err().0, 1);
+    assert_eq!(product_all(&["1000", "1000", "1000", "1000"]), Err(vec![(3, MultiplyError::Overflow)]));
     //endregion
 
-    //region 18.4.5.包裹错误
-    println!("\n\n=====18.4.5.包裹错误=====");
-    // 把错误装箱这种做法也可以改成把它包裹到你自己的错误类型中去。
-    type ResultV6<T> = std::result::Result<T, DoubleErrorV6>;
-    #[derive(Debug)]
-    enum DoubleErrorV6 {
-        EmptyVec,
-        // 在这个错误类型中，采用 `parse` 的错误类型中 `Err` 部分的实现
+    //region 18.3.扩展：定点小数
+    println!("\n\n=====18.3.扩展：定点小数=====");
+    // multiply 的例子只有整数，但算钱的时候是 "19.99" * "3" 这样的价格。
+    // 用浮点数会有误差，所以这里用定点小数：units 是放大 10^scale 倍后的整数，scale 可以配置。
+    // 解析时小数位比 scale 多就拒绝；相乘时多出来的小数位按指定的舍入方式处理。
+    // 整数部分和小数部分还是用 parse::<i64>() 解析，通过 From<ParseIntError> 接上原来的 ? 流程。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Decimal {
+        units: i64,
+        scale: u32,
+    }
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Rounding {
+        // 直接截掉（向零舍入）
+        Down,
+        // 四舍五入，.5 远离零
+        HalfUp,
+        // 银行家舍入，.5 舍入到偶数
+        HalfEven,
+    }
+    #[derive(Debug, Clone, PartialEq)]
+    enum DecimalError {
         Parse(ParseIntError),
+        // 小数点后面出现了正负号之类的
+        Malformed(String),
+        TooManyFractionDigits { allowed: u32, found: usize },
+        Overflow,
     }
-    impl fmt::Display for DoubleErrorV6 {
+    impl fmt::Display for DecimalError {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match *self {
-                DoubleErrorV6::EmptyVec => write!(f, "Please use a vector with at least one element"),
-                DoubleErrorV6::Parse(ref e) => e.fmt(f),
+                DecimalError::Parse(ref e) => e.fmt(f),
+                DecimalError::Malformed(ref s) => write!(f, "{:?} is not a decimal number", s),
+                DecimalError::TooManyFractionDigits { allowed, found } =>
+                    write!(f, "at most {} fraction digits are allowed, found {}", allowed, found),
+                DecimalError::Overflow => write!(f, "decimal value overflowed"),
             }
         }
     }
-    impl error::Error for DoubleErrorV6 {
+    impl error::Error for DecimalError {
         fn source(&self) -> Option<&(dyn error::Error + 'static)> {
             match *self {
-                DoubleErrorV6::EmptyVec => None,
-                DoubleErrorV6::Parse(ref e) => Some(e),
+                DecimalError::Parse(ref e) => Some(e),
+                _ => None,
             }
         }
     }
-    // 实现从 `ParseIntError` 到 `DoubleError` 的转换
-    // 在使用 ？ 时，或者一个 'ParseIntError' 需要转换成 'DoubleErrorV6' 时，它会被自动调用。
-    impl From<ParseIntError> for DoubleErrorV6 {
-        fn from(err: ParseIntError) -> DoubleErrorV6 {
-            DoubleErrorV6::Parse(err)
+    impl From<ParseIntError> for DecimalError {
+        fn from(err: ParseIntError) -> DecimalError {
+            DecimalError::Parse(err)
         }
     }
-    fn double_first_v6(vec: Vec<&str>) -> ResultV6<i32> {
-        let first = vec.first().ok_or(DoubleErrorV6::EmptyVec)?;
-        let parsed = first.parse::<i32>()?;
-        Ok(2 * parsed)
+    fn pow10(exponent: u32) -> Result<i64, DecimalError> {
+        10i64.checked_pow(exponent).ok_or(DecimalError::Overflow)
     }
-    fn print_v6(result: ResultV6<i32>) {
-        match result {
-            Ok(n) => println!("The first doubled is {}", n),
-            Err(err) => println!("Error: {}", err),
+    impl Decimal {
+        fn parse(s: &str, scale: u32) -> Result<Decimal, DecimalError> {
+            let negative = s.starts_with('-');
+            let unsigned = s.strip_prefix(['+', '-']).unwrap_or(s);
+            let (whole_str, fraction_str) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+            // 符号只能出现一次，而且只能在最前面："--5"、"-+5"、"1.-5" 都不算数
+            if whole_str.starts_with(['+', '-']) || fraction_str.starts_with(['+', '-']) {
+                return Err(DecimalError::Malformed(s.to_string()));
+            }
+            if fraction_str.len() > scale as usize {
+                return Err(DecimalError::TooManyFractionDigits { allowed: scale, found: fraction_str.len() });
+            }
+            // ".5"、"-.5" 省略了整数部分，按 0 算；只有 "." 的话两边都是空的，照样报错
+            let whole = if whole_str.is_empty() && !fraction_str.is_empty() { 0 } else { whole_str.parse::<i64>()? };
+            let fraction = if fraction_str.is_empty() { 0 } else { fraction_str.parse::<i64>()? };
+            // 小数部分补零到 scale 位，比如 scale 为 2 时 ".5" 表示 50
+            let fraction = fraction
+                .checked_mul(pow10(scale - fraction_str.len() as u32)?)
+                .ok_or(DecimalError::Overflow)?;
+            let units = whole
+                .checked_mul(pow10(scale)?)
+                .and_then(|units| units.checked_add(fraction))
+                .ok_or(DecimalError::Overflow)?;
+            Ok(Decimal { units: if negative { -units } else { units }, scale })
+        }
+        // 结果保留 self 的 scale
+        fn mul(self, rhs: Decimal, rounding: Rounding) -> Result<Decimal, DecimalError> {
+            let raw = self.units as i128 * rhs.units as i128;
+            let divisor = pow10(rhs.scale)? as i128;
+            let (quotient, remainder) = (raw / divisor, raw % divisor);
+            // 比较余数的两倍和除数，判断舍掉的部分是不是超过一半
+            let twice = remainder.abs() * 2;
+            let away_from_zero = match rounding {
+                Rounding::Down => false,
+                Rounding::HalfUp => twice >= divisor,
+                Rounding::HalfEven => twice > divisor || (twice == divisor && quotient % 2 != 0),
+            };
+            let rounded = if away_from_zero { quotient + raw.signum() } else { quotient };
+            let units = i64::try_from(rounded).map_err(|_| DecimalError::Overflow)?;
+            Ok(Decimal { units, scale: self.scale })
         }
     }
+    impl fmt::Display for Decimal {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let divisor = 10u64.pow(self.scale);
+            let sign = if self.units < 0 { "-" } else { "" };
+            let abs = self.units.unsigned_abs();
+            if self.scale == 0 {
+                write!(f, "{}{}", sign, abs)
+            } else {
+                write!(f, "{}{}.{:0width$}", sign, abs / divisor, abs % divisor, width = self.scale as usize)
+            }
+        }
+    }
+    fn multiply_money(first_number_str: &str, second_number_str: &str, scale: u32, rounding: Rounding)
+                      -> Result<Decimal, DecimalError> {
+        let first_number = Decimal::parse(first_number_str, scale)?;
+        let second_number = Decimal::parse(second_number_str, scale)?;
+        first_number.mul(second_number, rounding)
+    }
 
-    let numbers = vec!["42", "93", "18"];
-    let empty = vec![];
-    let strings = vec!["tofu", "93", "18"];
-
-    print_v6(double_first_v6(numbers));
-    print_v6(double_first_v6(empty));
-    print_v6(double_first_v6(strings));
+    for (a, b) in [("19.99", "3"), ("0.05", "0.5"), ("19.999", "3"), ("1x.00", "2")] {
+        report_result(&format!("{} * {} =", a, b), multiply_money(a, b, 2, Rounding::HalfUp).as_ref(), ReportStyle::Plain);
+    }
+    let money = |s: &str| multiply_money(s, "1", 2, Rounding::Down).map(|d| d.to_string());
+    assert_eq!(multiply_money("19.99", "3", 2, Rounding::HalfUp).map(|d| d.to_string()), Ok("59.97".to_string()));
+    assert_eq!(multiply_money("0.05", "0.5", 2, Rounding::HalfUp).map(|d| d.units), Ok(3));
+    assert_eq!(multiply_money("0.05", "0.5", 2, Rounding::HalfEven).map(|d| d.units), Ok(2));
+    assert_eq!(multiply_money("0.07", "0.5", 2, Rounding::HalfEven).map(|d| d.units), Ok(4));
+    assert_eq!(multiply_money("-0.05", "0.5", 2, Rounding::HalfUp).map(|d| d.to_string()), Ok("-0.03".to_string()));
+    assert_eq!(multiply_money("0.05", "0.5", 2, Rounding::Down).map(|d| d.units), Ok(2));
+    assert_eq!(money("-0.5"), Ok("-0.50".to_string()));
+    assert_eq!(money(".5"), Ok("0.50".to_string()));
+    assert_eq!(money("-.5"), Ok("-0.50".to_string()));
+    assert!(matches!(money("."), Err(DecimalError::Parse(_))));
+    assert_eq!(money("19.999"), Err(DecimalError::TooManyFractionDigits { allowed: 2, found: 3 }));
+    assert!(matches!(money("1x.00"), Err(DecimalError::Parse(_))));
+    assert_eq!(money("1.-5"), Err(DecimalError::Malformed("1.-5".to_string())));
+    assert_eq!(money("--5"), Err(DecimalError::Malformed("--5".to_string())));
+    assert_eq!(money("-+5"), Err(DecimalError::Malformed("-+5".to_string())));
+    assert_eq!(multiply_money("92233720368547758.07", "2", 2, Rounding::Down), Err(DecimalError::Overflow));
     //endregion
 
-    //region 18.5.遍历Result
-    println!("\n\n=====18.5.遍历Result=====");
-    // 有可能 map 操作失败，可以用 filter_map 过滤掉为 None 的所有结果
-    let strings = vec!["tofu", "93", "18"];
-    let numbers: Vec<_> = strings
-        .into_iter()
-        .filter_map(|s| s.parse::<i32>().ok())
-        .collect();
-    println!("遍历的结果是：{:?}", numbers);
+    //region 18.扩展：统一的结果输出
+    println!("\n\n=====18.扩展：统一的结果输出=====");
+    // print_v1 ~ print_v6、print_double_v3、print_double_v5 几乎都是同一个 match。
+    // 这里写一个对 Result<T, E> 泛型的 report_result，T 只要能 Display，E 只要实现了 Error，
+    // 支持三种输出：Plain 和原来一样；Verbose 会沿着 source() 把整条错误链打出来；Json 输出一行 JSON。
+    // E 允许是 dyn Error 这样的动态大小类型，装箱的错误用 .map_err(|e| e.as_ref()) 传进来即可。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum ReportStyle {
+        Plain,
+        Verbose,
+        Json,
+    }
+    // 沿着 source() 收集所有下层原因，不包括错误本身
+    fn causes<E: error::Error + ?Sized>(e: &E) -> Vec<String> {
+        let mut causes = Vec::new();
+        let mut source = e.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        causes
+    }
+    fn json_string(s: &str) -> String {
+        let mut escaped = String::from("\"");
+        for c in s.chars() {
+            match c {
+                '"' => escaped.push_str("\\\""),
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                c => escaped.push(c),
+            }
+        }
+        escaped.push('"');
+        escaped
+    }
+    fn render<T: fmt::Display, E: error::Error + ?Sized>(label: &str, result: Result<T, &E>, style: ReportStyle) -> String {
+        match (style, result) {
+            (ReportStyle::Json, Ok(value)) => format!("{{\"ok\":true,\"value\":{}}}", json_string(&value.to_string())),
+            (ReportStyle::Json, Err(e)) => {
+                let cause_list: Vec<String> = causes(e).iter().map(|cause| json_string(cause)).collect();
+                format!("{{\"ok\":false,\"error\":{},\"causes\":[{}]}}", json_string(&e.to_string()), cause_list.join(","))
+            }
+            (_, Ok(value)) => format!("{} {}", label, value),
+            (ReportStyle::Plain, Err(e)) => format!("Error: {}", e),
+            (ReportStyle::Verbose, Err(e)) => {
+                let mut text = format!("Error: {}", e);
+                for cause in causes(e) {
+                    text.push_str(&format!("\n    caused by: {}", cause));
+                }
+                text
+            }
+        }
+    }
+    fn report_result<T: fmt::Display, E: error::Error + ?Sized>(label: &str, result: Result<T, &E>, style: ReportStyle) {
+        println!("{}", render(label, result, style));
+    }
+
+    for style in [ReportStyle::Plain, ReportStyle::Verbose, ReportStyle::Json] {
+        report_result("n is", multiply_v5("10", "2").as_ref(), style);
+        report_result("n is", multiply_v5("z", "2").as_ref(), style);
+        report_result("The first doubled is", double_first_v5(vec![]).as_ref().map_err(|e| e.as_ref()), style);
+    }
+    assert_eq!(render("n is", multiply_v4("10", "2", &StdParse).as_ref(), ReportStyle::Plain), "n is 20");
+    assert_eq!(render("n is", multiply_v4("z", "2", &StdParse).as_ref(), ReportStyle::Plain), "Error: invalid digit found in string");
+    assert_eq!(render("n is", multiply_v5("z", "2").as_ref(), ReportStyle::Verbose),
+               "Error: argument 1 (\"z\") is not a valid number\n    caused by: invalid digit found in string");
+    assert_eq!(render("n is", multiply_v5("z", "2").as_ref(), ReportStyle::Json),
+               r#"{"ok":false,"error":"argument 1 (\"z\") is not a valid number","causes":["invalid digit found in string"]}"#);
+    assert_eq!(render("n is", checked_multiply_v4("10", "2").as_ref(), ReportStyle::Json), r#"{"ok":true,"value":"20"}"#);
     //endregion
 }
+
+//region 18.3.扩展：泛型的multiply
+// 从 main 里挪出来的部分：单元测试只能访问模块级的条目
+use std::error;
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+trait CheckedMul: Sized {
+    fn checked_product(self, rhs: Self) -> Option<Self>;
+}
+impl CheckedMul for i32 {
+    fn checked_product(self, rhs: i32) -> Option<i32> {
+        self.checked_mul(rhs)
+    }
+}
+impl CheckedMul for i64 {
+    fn checked_product(self, rhs: i64) -> Option<i64> {
+        self.checked_mul(rhs)
+    }
+}
+impl CheckedMul for u128 {
+    fn checked_product(self, rhs: u128) -> Option<u128> {
+        self.checked_mul(rhs)
+    }
+}
+// 浮点数不会 panic，但乘出无穷大也算溢出
+impl CheckedMul for f64 {
+    fn checked_product(self, rhs: f64) -> Option<f64> {
+        let product = self * rhs;
+        if product.is_finite() || !(self.is_finite() && rhs.is_finite()) {
+            Some(product)
+        } else {
+            None
+        }
+    }
+}
+#[derive(Debug, PartialEq)]
+enum GenericMultiplyError<E> {
+    Parse(E),
+    Overflow,
+}
+impl<E: fmt::Display> fmt::Display for GenericMultiplyError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GenericMultiplyError::Parse(ref e) => e.fmt(f),
+            GenericMultiplyError::Overflow => write!(f, "multiplication overflowed"),
+        }
+    }
+}
+impl<E: error::Error + 'static> error::Error for GenericMultiplyError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            // Display 已经是里面那个解析错误的了，错误链从它的 source() 接着往下找，和 MultiplyError 一样
+            GenericMultiplyError::Parse(ref e) => e.source(),
+            GenericMultiplyError::Overflow => None,
+        }
+    }
+}
+fn multiply_generic<T>(first_number_str: &str, second_number_str: &str)
+                       -> Result<T, GenericMultiplyError<<T as FromStr>::Err>>
+    where T: FromStr + CheckedMul
+{
+    let first_number = first_number_str.parse::<T>().map_err(GenericMultiplyError::Parse)?;
+    let second_number = second_number_str.parse::<T>().map_err(GenericMultiplyError::Parse)?;
+    first_number.checked_product(second_number).ok_or(GenericMultiplyError::Overflow)
+}
+
+// 自定义类型：倍数，写成 "3x" 这样，两个倍数相乘还是倍数
+#[derive(Debug, PartialEq)]
+struct Factor(u32);
+#[derive(Debug, PartialEq)]
+enum FactorError {
+    MissingSuffix,
+    Number(ParseIntError),
+}
+impl fmt::Display for FactorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FactorError::MissingSuffix => write!(f, "expected a factor ending in `x`"),
+            FactorError::Number(ref e) => write!(f, "bad factor: {}", e),
+        }
+    }
+}
+impl error::Error for FactorError {}
+impl FromStr for Factor {
+    type Err = FactorError;
+    fn from_str(s: &str) -> Result<Factor, FactorError> {
+        let digits = s.strip_suffix('x').ok_or(FactorError::MissingSuffix)?;
+        digits.parse().map(Factor).map_err(FactorError::Number)
+    }
+}
+impl CheckedMul for Factor {
+    fn checked_product(self, rhs: Factor) -> Option<Factor> {
+        self.0.checked_mul(rhs.0).map(Factor)
+    }
+}
+//endregion
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiply_generic_integers() {
+        assert_eq!(multiply_generic::<i64>("100000", "100000"), Ok(10_000_000_000));
+        assert_eq!(multiply_generic::<u128>("18446744073709551616", "4"), Ok(73786976294838206464));
+        assert_eq!(multiply_generic::<i32>("100000", "100000"), Err(GenericMultiplyError::Overflow));
+    }
+
+    #[test]
+    fn multiply_generic_parse_int_error() {
+        let expected = "t".parse::<i32>().unwrap_err();
+        assert_eq!(multiply_generic::<i32>("t", "2"), Err(GenericMultiplyError::Parse(expected.clone())));
+        assert_eq!(multiply_generic::<i32>("2", "t"), Err(GenericMultiplyError::Parse(expected)));
+        assert!(matches!(multiply_generic::<u128>("-1", "4"), Err(GenericMultiplyError::Parse(_))));
+    }
+
+    #[test]
+    fn multiply_generic_error_is_not_repeated_as_its_source() {
+        let err = multiply_generic::<i32>("t", "2").unwrap_err();
+        assert_eq!(err.to_string(), "invalid digit found in string");
+        assert!(error::Error::source(&err).is_none());
+    }
+
+    #[test]
+    fn multiply_generic_floats() {
+        assert_eq!(multiply_generic::<f64>("2.5", "4"), Ok(10.0));
+        assert_eq!(multiply_generic::<f64>("1e308", "10"), Err(GenericMultiplyError::Overflow));
+        // 本来就是无穷大的输入不算溢出
+        assert_eq!(multiply_generic::<f64>("inf", "2"), Ok(f64::INFINITY));
+    }
+
+    #[test]
+    fn multiply_generic_float_parse_error() {
+        let expected = "2.5x".parse::<f64>().unwrap_err();
+        assert_eq!(multiply_generic::<f64>("2.5x", "4"), Err(GenericMultiplyError::Parse(expected)));
+        assert!(matches!(multiply_generic::<f64>("4", ""), Err(GenericMultiplyError::Parse(_))));
+    }
+
+    #[test]
+    fn multiply_generic_user_type() {
+        assert_eq!(multiply_generic::<Factor>("3x", "4x"), Ok(Factor(12)));
+        assert_eq!(multiply_generic::<Factor>("3x", "4"), Err(GenericMultiplyError::Parse(FactorError::MissingSuffix)));
+        assert!(matches!(multiply_generic::<Factor>("3x", "yx"), Err(GenericMultiplyError::Parse(FactorError::Number(_)))));
+        assert_eq!(multiply_generic::<Factor>("65536x", "65536x"), Err(GenericMultiplyError::Overflow));
+    }
+}